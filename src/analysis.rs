@@ -0,0 +1,285 @@
+use std::fmt::Display;
+
+use crate::{
+    error::RuntimeError, BinaryOp, File, Function, Location, Parameter, Pattern, Piece, Symbol,
+    Term,
+};
+
+/// Finds the errors that can be told without running the program: unknown
+/// identifiers, calls to function literals with the wrong number of
+/// arguments and divisions by a literal zero.
+///
+/// Unlike evaluating it, this goes through the whole program, so every one
+/// of them is reported, in the order they appear.
+pub fn analyze(file: &File) -> Vec<RuntimeError> {
+    let mut errors = Vec::new();
+    walk(&file.expression, &mut Vec::new(), &mut errors);
+    errors
+}
+
+/// A name in scope, with the function it's bound to when that is known.
+type Binding<'a> = (Symbol, Option<&'a Function>);
+
+fn walk<'a>(term: &'a Term, bound: &mut Vec<Binding<'a>>, errors: &mut Vec<RuntimeError>) {
+    match term {
+        Term::Var(var) => {
+            if lookup(bound, var.text).is_none() {
+                errors.push(RuntimeError::unknow_identifier(var));
+            }
+        }
+
+        Term::Assign(t) => {
+            if lookup(bound, t.name.text).is_none() {
+                errors.push(RuntimeError::unknow_identifier(&t.name));
+            }
+            walk(&t.value, bound, errors);
+        }
+
+        Term::Str(t) => {
+            for piece in t.pieces().into_iter().flatten() {
+                if let Piece::Expr(term) = piece {
+                    let mut inner = bound.clone();
+                    walk(&term, &mut inner, errors);
+                }
+            }
+        }
+
+        Term::Binary(t) => {
+            walk(&t.lhs, bound, errors);
+            walk(&t.rhs, bound, errors);
+            if matches!(t.op, BinaryOp::Div | BinaryOp::Rem)
+                && matches!(&t.rhs, Term::Int(n) if n.value == 0)
+            {
+                errors.push(RuntimeError::division_by_zero(t.location.clone()));
+            }
+        }
+
+        Term::Call(t) => {
+            let fun = match &t.callee {
+                Term::Function(fun) => Some(&**fun),
+                Term::Var(var) => lookup(bound, var.text).flatten(),
+                _ => None,
+            };
+            for term in term.children() {
+                walk(term, bound, errors);
+            }
+            if let Some(fun) = fun.filter(|fun| fun.parameters.len() != t.arguments.len()) {
+                errors.push(RuntimeError::invalid_number_of_arguments(
+                    fun,
+                    t.arguments.len(),
+                    &t.location,
+                ));
+            }
+        }
+
+        Term::Let(t) => {
+            let len = bound.len();
+            let mut names = Vec::new();
+            t.name.names(&mut names);
+            match &t.value {
+                // Functions are bound in their own scope, so they can recurse,
+                // and see the functions bound by the lets after them, unless
+                // they already see something else by those names.
+                Term::Function(fun) => {
+                    bound.extend(names.iter().map(|name| (*name, Some(&**fun))));
+                    let before = bound.len();
+                    for (name, fun) in later_functions(&t.next) {
+                        if lookup(bound, name).is_none() {
+                            bound.push((name, Some(fun)));
+                        }
+                    }
+                    walk(&t.value, bound, errors);
+                    bound.truncate(before);
+                }
+                value => {
+                    let start = errors.len();
+                    walk(value, bound, errors);
+                    let found = errors.split_off(start);
+                    errors.extend(found.into_iter().map(|error| {
+                        names
+                            .iter()
+                            .fold(error, |error, name| error.defining(*name))
+                    }));
+                    bound.extend(names.iter().map(|name| (*name, None)));
+                }
+            }
+            walk(&t.next, bound, errors);
+            bound.truncate(len);
+        }
+
+        Term::Function(fun) => {
+            let len = bound.len();
+            bound.extend(fun.parameters.iter().map(|p| (p.text, None)));
+            walk(&fun.value, bound, errors);
+            bound.truncate(len);
+        }
+
+        Term::MatchTag(t) => {
+            walk(&t.value, bound, errors);
+            for arm in &t.arms {
+                let len = bound.len();
+                bound.extend(arm.binding.as_ref().map(|p| (p.text, None)));
+                walk(&arm.then, bound, errors);
+                bound.truncate(len);
+            }
+        }
+
+        _ => {
+            for term in term.children() {
+                walk(term, bound, errors);
+            }
+        }
+    }
+}
+
+/// The functions bound by the chain of lets starting at `term`.
+fn later_functions(mut term: &Term) -> Vec<(Symbol, &Function)> {
+    let mut found = Vec::new();
+    while let Term::Let(t) = term {
+        if let (Pattern::Var(var), Term::Function(fun)) = (&t.name, &t.value) {
+            found.push((var.text, &**fun));
+        }
+        term = &t.next;
+    }
+    found
+}
+
+/// What `name` is bound to, the innermost binding first.
+fn lookup<'a>(bound: &[Binding<'a>], name: Symbol) -> Option<Option<&'a Function>> {
+    bound
+        .iter()
+        .rev()
+        .find(|(bound, _)| *bound == name)
+        .map(|(_, fun)| *fun)
+}
+
+/// Something suspicious about a program that doesn't stop it from running.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    pub location: Location,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Finds the `let`s binding names that are never used afterwards, in the
+/// order they appear. Names starting with `_`, like in `let _ = print(a)`,
+/// are meant not to be used and aren't reported.
+pub fn warnings(file: &File) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    unused(&file.expression, &mut Vec::new(), &mut warnings);
+    warnings.sort_by_key(|warning| warning.location.start);
+    warnings
+}
+
+/// A name in scope, with the `let` parameter that bound it, if any.
+struct Usage {
+    name: Symbol,
+    binding: Option<Parameter>,
+    used: bool,
+}
+
+impl Usage {
+    fn new(name: Symbol, binding: Option<Parameter>) -> Self {
+        Self {
+            name,
+            binding,
+            used: false,
+        }
+    }
+}
+
+fn unused(term: &Term, scope: &mut Vec<Usage>, warnings: &mut Vec<Warning>) {
+    match term {
+        Term::Var(var) => mark_used(scope, var.text),
+
+        Term::Assign(t) => {
+            mark_used(scope, t.name.text);
+            unused(&t.value, scope, warnings);
+        }
+
+        Term::Str(t) => {
+            for piece in t.pieces().into_iter().flatten() {
+                if let Piece::Expr(term) = piece {
+                    unused(&term, scope, warnings);
+                }
+            }
+        }
+
+        Term::Let(t) => {
+            let len = scope.len();
+            let mut bindings = Vec::new();
+            parameters(&t.name, &mut bindings);
+            let bindings = bindings
+                .into_iter()
+                .map(|p| Usage::new(p.text, Some(p.clone())));
+            match &t.value {
+                Term::Function(_) => {
+                    scope.extend(bindings);
+                    unused(&t.value, scope, warnings);
+                }
+                value => {
+                    unused(value, scope, warnings);
+                    scope.extend(bindings);
+                }
+            }
+            unused(&t.next, scope, warnings);
+
+            for usage in scope.drain(len..) {
+                match usage.binding {
+                    Some(p) if !usage.used && !p.text.as_str().starts_with('_') => {
+                        warnings.push(Warning {
+                            message: format!("'{}' nunca é usado", p.text),
+                            location: p.location,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Term::Function(fun) => {
+            let len = scope.len();
+            scope.extend(fun.parameters.iter().map(|p| Usage::new(p.text, None)));
+            unused(&fun.value, scope, warnings);
+            scope.truncate(len);
+        }
+
+        Term::MatchTag(t) => {
+            unused(&t.value, scope, warnings);
+            for arm in &t.arms {
+                let len = scope.len();
+                scope.extend(arm.binding.as_ref().map(|p| Usage::new(p.text, None)));
+                unused(&arm.then, scope, warnings);
+                scope.truncate(len);
+            }
+        }
+
+        _ => {
+            for term in term.children() {
+                unused(term, scope, warnings);
+            }
+        }
+    }
+}
+
+fn mark_used(scope: &mut [Usage], name: Symbol) {
+    if let Some(usage) = scope.iter_mut().rev().find(|usage| usage.name == name) {
+        usage.used = true;
+    }
+}
+
+/// The parameters a `let` pattern binds.
+fn parameters<'a>(pattern: &'a Pattern, found: &mut Vec<&'a Parameter>) {
+    match pattern {
+        Pattern::Var(p) => found.push(p),
+        Pattern::Tuple(t) => {
+            parameters(&t.first, found);
+            parameters(&t.second, found);
+        }
+    }
+}
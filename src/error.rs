@@ -2,15 +2,22 @@ use std::fmt::Display;
 
 use miette::{Diagnostic, LabeledSpan};
 
-use crate::{Function, Location, Var};
+use crate::{Function, Location, Symbol, Val, Var};
 
 #[derive(Debug)]
 pub enum ErrorKind {
     ArgumentError,
+    TypeMismatch,
     DivisionByZero,
+    IntegerOverflow,
+    IndexOutOfBounds,
+    RecursionLimit,
+    Timeout,
     UnknowIdentifier(Var),
+    UseBeforeDefinition(Var),
     InvalidBinaryOperation,
-    InvalidNumberOfArguments(Function, Location),
+    InvalidCondition,
+    InvalidNumberOfArguments(Location, Location),
 }
 
 #[derive(Debug)]
@@ -18,6 +25,8 @@ pub struct RuntimeError {
     message: String,
     location: Location,
     kind: ErrorKind,
+    /// Line and column of `location`, once the source is known.
+    position: Option<(usize, usize)>,
 }
 
 impl RuntimeError {
@@ -26,14 +35,50 @@ impl RuntimeError {
             message: message.into(),
             location,
             kind: ErrorKind::ArgumentError,
+            position: None,
         }
     }
 
-    pub fn unknow_identifier(var: Var) -> Self {
+    pub fn type_mismatch(expected: &str, found: &Val, location: Location) -> Self {
+        Self {
+            message: format!("esperava {expected}, encontrou {}", found.type_name()),
+            location,
+            kind: ErrorKind::TypeMismatch,
+            position: None,
+        }
+    }
+
+    pub fn invalid_condition(found: &Val, location: Location) -> Self {
+        Self {
+            kind: ErrorKind::InvalidCondition,
+            ..Self::type_mismatch("booleano", found, location)
+        }
+    }
+
+    pub fn unhashable(kind: &str, location: Location) -> Self {
+        Self::new(format!("{kind} não pode ser usado como chave"), location)
+    }
+
+    pub fn unknow_identifier(var: &Var) -> Self {
         Self {
             message: "identificador não encontrado".into(),
             location: var.location.clone(),
-            kind: ErrorKind::UnknowIdentifier(var),
+            kind: ErrorKind::UnknowIdentifier(var.clone()),
+            position: None,
+        }
+    }
+
+    /// Turns an unknown `name` into a use before its definition, for errors
+    /// raised while evaluating the value bound to `name`.
+    pub fn defining(self, name: Symbol) -> Self {
+        match self.kind {
+            ErrorKind::UnknowIdentifier(var) if var.text == name => Self {
+                message: format!("uso de '{name}' antes de sua definição"),
+                location: var.location.clone(),
+                kind: ErrorKind::UseBeforeDefinition(var),
+                position: None,
+            },
+            kind => Self { kind, ..self },
         }
     }
 
@@ -42,6 +87,43 @@ impl RuntimeError {
             message: "divisão por zero".into(),
             location: loc,
             kind: ErrorKind::DivisionByZero,
+            position: None,
+        }
+    }
+
+    pub fn integer_overflow(loc: Location) -> Self {
+        Self {
+            message: "estouro de inteiro".into(),
+            location: loc,
+            kind: ErrorKind::IntegerOverflow,
+            position: None,
+        }
+    }
+
+    pub fn index_out_of_bounds(loc: Location) -> Self {
+        Self {
+            message: "índice fora dos limites".into(),
+            location: loc,
+            kind: ErrorKind::IndexOutOfBounds,
+            position: None,
+        }
+    }
+
+    pub fn recursion_limit(loc: Location) -> Self {
+        Self {
+            message: "limite de recursão excedido".into(),
+            location: loc,
+            kind: ErrorKind::RecursionLimit,
+            position: None,
+        }
+    }
+
+    pub fn timeout(loc: Location) -> Self {
+        Self {
+            message: "tempo limite excedido".into(),
+            location: loc,
+            kind: ErrorKind::Timeout,
+            position: None,
         }
     }
 
@@ -50,21 +132,105 @@ impl RuntimeError {
             message: "operação inválida".into(),
             location: loc,
             kind: ErrorKind::InvalidBinaryOperation,
+            position: None,
+        }
+    }
+
+    pub fn invalid_operands(loc: Location, lhs: &str, rhs: &str) -> Self {
+        Self {
+            message: format!("operação inválida entre {lhs} e {rhs}"),
+            location: loc,
+            kind: ErrorKind::InvalidBinaryOperation,
+            position: None,
         }
     }
 
-    pub fn invalid_number_of_arguments(fun: Function, loc: Location) -> Self {
+    pub fn invalid_number_of_arguments(fun: &Function, given: usize, loc: &Location) -> Self {
+        let parameters = match (fun.parameters.first(), fun.parameters.last()) {
+            (Some(first), Some(last)) => Location {
+                start: first.location.start,
+                end: last.location.end,
+            },
+            _ => Location {
+                start: fun.location.start,
+                end: fun.location.start + 2,
+            },
+        };
+
         Self {
-            message: "número de argumentos inválidos".into(),
+            message: format!(
+                "esperava {}, recebeu {given}",
+                arguments(fun.parameters.len())
+            ),
             location: loc.clone(),
-            kind: ErrorKind::InvalidNumberOfArguments(fun, loc),
+            kind: ErrorKind::InvalidNumberOfArguments(parameters, loc.clone()),
+            position: None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// Line and column of the error, if [`RuntimeError::with_source`] was given
+    /// the source.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.position
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// A code for the kind of error that, unlike the message, won't change.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::ArgumentError => "E_ARGUMENT",
+            ErrorKind::TypeMismatch
+            | ErrorKind::InvalidBinaryOperation
+            | ErrorKind::InvalidCondition => "E_TYPE",
+            ErrorKind::DivisionByZero => "E_DIV_ZERO",
+            ErrorKind::IntegerOverflow => "E_OVERFLOW",
+            ErrorKind::IndexOutOfBounds => "E_INDEX",
+            ErrorKind::RecursionLimit => "E_RECURSION",
+            ErrorKind::Timeout => "E_TIMEOUT",
+            ErrorKind::UnknowIdentifier(_) => "E_UNKNOWN_IDENT",
+            ErrorKind::UseBeforeDefinition(_) => "E_USE_BEFORE_DEF",
+            ErrorKind::InvalidNumberOfArguments(..) => "E_ARITY",
         }
     }
+
+    /// Translates the error location into a line and column of `source`, so
+    /// it can be reported without rendering the source code.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.position = source.get(..self.location.start).map(|before| {
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+            (line, column)
+        });
+        self
+    }
+}
+
+fn arguments(count: usize) -> String {
+    match count {
+        1 => "1 argumento".into(),
+        _ => format!("{count} argumentos"),
+    }
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        match self.position {
+            Some((line, column)) => {
+                write!(f, "erro na linha {line}, coluna {column}: {}", self.message)
+            }
+            None => write!(f, "{}", self.message),
+        }
     }
 }
 
@@ -74,8 +240,13 @@ impl Diagnostic for RuntimeError {
     fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
         match self.kind {
             ErrorKind::ArgumentError
+            | ErrorKind::TypeMismatch
             | ErrorKind::InvalidBinaryOperation
-            | ErrorKind::DivisionByZero => Some(Box::new(
+            | ErrorKind::DivisionByZero
+            | ErrorKind::IntegerOverflow
+            | ErrorKind::IndexOutOfBounds
+            | ErrorKind::RecursionLimit
+            | ErrorKind::Timeout => Some(Box::new(
                 [LabeledSpan::at(
                     self.location.start..self.location.end,
                     self.message.clone(),
@@ -83,27 +254,28 @@ impl Diagnostic for RuntimeError {
                 .into_iter(),
             )),
 
-            ErrorKind::UnknowIdentifier(ref var) => Some(Box::new(
+            ErrorKind::InvalidCondition => Some(Box::new(
                 [LabeledSpan::at(
-                    var.location.start..var.location.end,
-                    self.message.clone(),
+                    self.location.start..self.location.end,
+                    "esta expressão precisa ser um booleano",
                 )]
                 .into_iter(),
             )),
 
-            ErrorKind::InvalidNumberOfArguments(ref fun, ref loc) => Some(Box::new(
+            ErrorKind::UnknowIdentifier(ref var) | ErrorKind::UseBeforeDefinition(ref var) => {
+                Some(Box::new(
+                    [LabeledSpan::at(
+                        var.location.start..var.location.end,
+                        self.message.clone(),
+                    )]
+                    .into_iter(),
+                ))
+            }
+
+            ErrorKind::InvalidNumberOfArguments(ref params, ref loc) => Some(Box::new(
                 [
                     LabeledSpan::at(loc.start..loc.end, "parâmetros informados"),
-                    LabeledSpan::at(
-                        if fun.parameters.is_empty() {
-                            fun.location.start..fun.location.start + 2
-                        } else {
-                            let first_param = fun.parameters.first().unwrap();
-                            let last_param = fun.parameters.last().unwrap();
-                            first_param.location.start..last_param.location.end
-                        },
-                        "argumentos esperados",
-                    ),
+                    LabeledSpan::at(params.start..params.end, "argumentos esperados"),
                 ]
                 .into_iter(),
             )),
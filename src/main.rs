@@ -1,407 +1,296 @@
 use std::{
-    cell::RefCell,
-    collections::HashMap,
-    env::args,
-    fmt::Display,
+    env::{self, args},
     fs,
-    io::{stdin, Read},
-    rc::Rc,
+    io::{stdin, stdout, BufRead, IsTerminal, Read, Write},
+    process, thread,
+    time::{Duration, Instant},
 };
 
-use serde::Deserialize;
-
-use crate::error::RuntimeError;
-
-mod error;
-
-#[derive(Debug, Deserialize)]
-pub struct File {
-    name: String,
-    expression: Term,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Location {
-    start: usize,
-    end: usize,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Int {
-    value: i32,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Bool {
-    value: bool,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Str {
-    value: String,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Print {
-    value: Term,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Binary {
-    rhs: Term,
-    op: BinaryOp,
-    lhs: Term,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub enum BinaryOp {
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Rem,
-    Eq,
-    Neq,
-    Lt,
-    Gt,
-    Lte,
-    Gte,
-    And,
-    Or,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct If {
-    condition: Term,
-    then: Term,
-    otherwise: Term,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Parameter {
-    text: String,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Let {
-    name: Parameter,
-    value: Term,
-    next: Term,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Var {
-    text: String,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Function {
-    parameters: Vec<Parameter>,
-    value: Term,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Call {
-    callee: Term,
-    arguments: Vec<Term>,
-    location: Location,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Tuple {
-    first: Term,
-    second: Term,
-    location: Location,
-}
+use rinha_compiladores::{
+    compile_with_tag, expand_int_literals, File, Format, Interpreter, Scope, Val,
+};
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct First {
-    value: Term,
-    location: Location,
-}
+/// Deeply nested programs recurse while being read, evaluated and dropped,
+/// so they run on a thread with a much larger stack than the main one. In
+/// megabytes, and can be changed with `RINHA_STACK_MB`.
+const STACK_MB: usize = 256;
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct Second {
-    value: Term,
-    location: Location,
-}
+fn main() {
+    let stack_mb = match env::var("RINHA_STACK_MB") {
+        Ok(mb) => mb.parse().ok(),
+        Err(_) => Some(STACK_MB),
+    };
+    let Some(stack_size) = stack_mb.and_then(|mb: usize| mb.checked_mul(1 << 20)) else {
+        eprintln!("RINHA_STACK_MB inválido");
+        process::exit(1);
+    };
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(tag = "kind")]
-pub enum Term {
-    Int(Int),
-    Str(Str),
-    Bool(Bool),
-    Print(Box<Print>),
-    Binary(Box<Binary>),
-    If(Box<If>),
-    Let(Box<Let>),
-    Var(Var),
-    Function(Box<Function>),
-    Call(Box<Call>),
-    Tuple(Box<Tuple>),
-    First(Box<First>),
-    Second(Box<Second>),
+    let child = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(start)
+        .expect("não foi possível criar a thread do interpretador");
+    if child.join().is_err() {
+        process::exit(101);
+    }
 }
 
-impl Term {
-    pub fn location(&self) -> &Location {
-        match self {
-            Term::Int(t) => &t.location,
-            Term::Str(t) => &t.location,
-            Term::Bool(t) => &t.location,
-            Term::Print(t) => &t.location,
-            Term::Binary(t) => &t.location,
-            Term::If(t) => &t.location,
-            Term::Let(t) => &t.location,
-            Term::Var(t) => &t.location,
-            Term::Function(t) => &t.location,
-            Term::Call(t) => &t.location,
-            Term::Tuple(t) => &t.location,
-            Term::First(t) => &t.location,
-            Term::Second(t) => &t.location,
+fn start() {
+    let mut file = None;
+    let mut memoize = false;
+    let mut bignum = false;
+    let mut format = Format::Text;
+    let mut repl = false;
+    let mut time = false;
+    let mut stats = false;
+    let mut dump_ast = false;
+    let mut print_returns_unit = false;
+    let mut trace = false;
+    let mut auto_curry = false;
+    let mut pure = false;
+    let mut explain = false;
+    let mut auto_print = false;
+    let mut tag_field = String::from("kind");
+    let mut int_width = None;
+    let mut json_errors = false;
+    let mut timeout = None;
+    let mut seed = 0;
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "--memoize" => memoize = true,
+            "--bignum" => bignum = true,
+            "--format=text" => format = Format::Text,
+            "--format=json" => format = Format::Json,
+            "--repl" => repl = true,
+            "--time" => time = true,
+            "--stats" => stats = true,
+            "--dump-ast" => dump_ast = true,
+            "--print-returns-unit" => print_returns_unit = true,
+            "--trace" => trace = true,
+            "--auto-curry" => auto_curry = true,
+            "--pure" => pure = true,
+            "--explain" => explain = true,
+            "--auto-print" => auto_print = true,
+            "--error-format=pretty" => json_errors = false,
+            "--error-format=json" => json_errors = true,
+            _ if arg.starts_with("--timeout=") => {
+                let seconds = arg["--timeout=".len()..].parse().ok();
+                timeout = Some(Duration::from_secs_f64(
+                    seconds
+                        .filter(|seconds: &f64| *seconds >= 0.0)
+                        .expect("--timeout inválido"),
+                ));
+            }
+            _ if arg.starts_with("--tag-field=") => {
+                tag_field = arg["--tag-field=".len()..].to_owned();
+            }
+            _ if arg.starts_with("--seed=") => {
+                seed = arg["--seed=".len()..].parse().expect("--seed inválido");
+            }
+            _ if arg.starts_with("--int-width=") => {
+                let width = arg["--int-width=".len()..].parse().ok();
+                int_width = Some(
+                    width
+                        .filter(|width| (1..=64).contains(width))
+                        .expect("--int-width inválido"),
+                );
+            }
+            _ => file = Some(arg),
         }
     }
-}
-
-#[derive(Debug, Clone)]
-pub enum Val {
-    Int(i32),
-    Bool(bool),
-    Str(String),
-    Tuple((Box<Val>, Box<Val>)),
-    Closure { fun: Function, env: Scope },
-}
 
-impl PartialEq for Val {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Val::Int(a), Val::Int(b)) => a == b,
-            (Val::Bool(a), Val::Bool(b)) => a == b,
-            (Val::Str(a), Val::Str(b)) => a == b,
-            (Val::Tuple(a), Val::Tuple(b)) => a == b,
-            _ => false,
-        }
+    let mut interpreter = Interpreter::new(stdout());
+    interpreter.memoize = memoize;
+    interpreter.format = format;
+    interpreter.bignum = bignum;
+    interpreter.print_returns_unit = print_returns_unit;
+    interpreter.trace = trace;
+    interpreter.auto_curry = auto_curry;
+    interpreter.pure = pure;
+    interpreter.explain = explain;
+    interpreter.int_width = int_width;
+    interpreter.seed = seed;
+    if let Ok(steps) = env::var("RINHA_MAX_STEPS") {
+        interpreter.max_steps = steps.parse().expect("RINHA_MAX_STEPS inválido");
     }
-}
 
-impl Display for Val {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Val::Int(i) => write!(f, "{i}"),
-            Val::Bool(true) => write!(f, "true"),
-            Val::Bool(false) => write!(f, "false"),
-            Val::Str(s) => write!(f, "{s}"),
-            Val::Tuple((fst, snd)) => write!(f, "({fst}, {snd})"),
-            Val::Closure { .. } => write!(f, "<#closure>"),
-        }
+    if repl || (file.is_none() && stdin().is_terminal()) {
+        return run_repl(&mut interpreter);
     }
-}
 
-#[derive(Debug, Default)]
-pub struct Scope {
-    parent: Option<Rc<Scope>>,
-    current: Rc<RefCell<HashMap<String, Val>>>,
-}
+    let file_given = file.is_some();
+    let (program, source) = match file {
+        Some(file) if file.ends_with(".rinha") => {
+            let source = fs::read_to_string(&file).expect("Arquivo não encontrado");
+            match rinha::parser::parse_or_report(&file, &expand_int_literals(&source)) {
+                Ok(ast) => (
+                    from_json(&serde_json::to_string(&ast).unwrap(), "kind"),
+                    Some(source),
+                ),
+                Err(error) => {
+                    print!("{:?}", miette::Report::new(error));
+                    process::exit(1);
+                }
+            }
+        }
+        Some(file) => {
+            let json = fs::read_to_string(file).expect("Arquivo não encontrado");
+            (from_json(&json, &tag_field), None)
+        }
+        None => {
+            let mut json = String::new();
+            stdin().lock().read_to_string(&mut json).unwrap();
+            (from_json(&json, &tag_field), None)
+        }
+    };
 
-impl Scope {
-    pub fn get(&self, var: &str) -> Option<Val> {
-        self.current
-            .borrow()
-            .get(var)
-            .cloned()
-            .or_else(|| self.parent.as_ref()?.get(var))
+    // Only programs read from a file leave stdin free for the program itself.
+    if file_given {
+        interpreter.input = Some(Box::new(stdin().lock()));
     }
 
-    pub fn set(&self, var: impl Into<String>, val: Val) {
-        self.current.borrow_mut().insert(var.into(), val);
+    if dump_ast {
+        serde_json::to_writer_pretty(stdout(), &program).unwrap();
+        println!();
+        return;
     }
-}
 
-impl Clone for Scope {
-    fn clone(&self) -> Self {
-        Scope {
-            parent: Some(Rc::new(Scope {
-                parent: self.parent.clone(),
-                current: self.current.clone(),
-            })),
-            current: Default::default(),
-        }
+    let start = Instant::now();
+    interpreter.deadline = timeout.map(|timeout| start + timeout);
+    let result = interpreter.run(&program);
+    if time {
+        eprintln!("tempo: {:.3}s", start.elapsed().as_secs_f64());
+    }
+    if stats {
+        eprintln!("{}", interpreter.stats());
+    }
+    if explain {
+        eprint!("{}", interpreter.explanation());
     }
-}
 
-fn eval(term: Term, scope: &Scope) -> Result<Val, RuntimeError> {
-    match term {
-        Term::Int(number) => Ok(Val::Int(number.value)),
-        Term::Str(str) => Ok(Val::Str(str.value)),
-        Term::Bool(bool) => Ok(Val::Bool(bool.value)),
-        Term::Print(print) => {
-            let val = eval(print.value, scope)?;
-            println!("{val}");
-            Ok(val)
+    match result {
+        Ok(val) if (auto_print || format == Format::Json) && !echoed(&interpreter, &val) => {
+            format.write(&mut stdout(), &val).unwrap();
         }
-        Term::Tuple(tuple) => Ok(Val::Tuple((
-            Box::new(eval(tuple.first, scope)?),
-            Box::new(eval(tuple.second, scope)?),
-        ))),
-        Term::First(t) => match eval(t.value, scope)? {
-            Val::Tuple((val, _)) => Ok(*val),
-            _ => Err(RuntimeError::new("não é uma tupla", t.location)),
-        },
-        Term::Second(t) => match eval(t.value, scope)? {
-            Val::Tuple((_, val)) => Ok(*val),
-            _ => Err(RuntimeError::new("não é uma tupla", t.location)),
-        },
-
-        Term::Binary(bin) => {
-            let lhs = eval(bin.lhs, scope)?;
-            let rhs = eval(bin.rhs, scope)?;
-
-            macro_rules! bin_op {
-                ($left:ident[$lhs:expr], $right:ident[$rhs:expr] -> $f:expr) => {
-                    match (lhs, rhs) {
-                        (Val::$left(lhs), Val::$right(rhs)) => $f(lhs, rhs),
-                        _ => Err(RuntimeError::invalid_binary_operation(bin.location)),
-                    }
-                };
-            }
-            #[allow(clippy::redundant_closure_call)]
-            match bin.op {
-                BinaryOp::Add => match (lhs, rhs) {
-                    (Val::Int(a), Val::Int(b)) => Ok(Val::Int(a + b)),
-                    (a, b) => Ok(Val::Str(format!("{a}{b}"))),
-                },
-                BinaryOp::Sub => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Int(a - b))),
-                BinaryOp::Mul => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Int(a * b))),
-                BinaryOp::Div => match (lhs, rhs) {
-                    (Val::Int(_), Val::Int(0)) => Err(RuntimeError::division_by_zero(bin.location)),
-                    (Val::Int(a), Val::Int(b)) => Ok(Val::Int(a / b)),
-                    _ => Err(RuntimeError::invalid_binary_operation(bin.location)),
-                },
-                BinaryOp::Rem => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Int(a % b))),
-                BinaryOp::And => bin_op!(Bool[lhs], Bool[rhs] -> |a, b| Ok(Val::Bool(a && b))),
-                BinaryOp::Or => bin_op!(Bool[lhs], Bool[rhs] -> |a, b| Ok(Val::Bool(a || b))),
-                BinaryOp::Lt => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Bool(a < b))),
-                BinaryOp::Lte => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Bool(a <= b))),
-                BinaryOp::Gt => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Bool(a > b))),
-                BinaryOp::Gte => bin_op!(Int[lhs], Int[rhs] -> |a, b| Ok(Val::Bool(a >= b))),
-                BinaryOp::Eq => match (lhs, rhs) {
-                    (Val::Int(a), Val::Int(b)) => Ok(Val::Bool(a == b)),
-                    (Val::Bool(a), Val::Bool(b)) => Ok(Val::Bool(a == b)),
-                    (Val::Str(a), Val::Str(b)) => Ok(Val::Bool(a == b)),
-                    _ => Err(RuntimeError::invalid_binary_operation(bin.location)),
-                },
-                BinaryOp::Neq => match (lhs, rhs) {
-                    (Val::Int(a), Val::Int(b)) => Ok(Val::Bool(a != b)),
-                    (Val::Bool(a), Val::Bool(b)) => Ok(Val::Bool(a != b)),
-                    (Val::Str(a), Val::Str(b)) => Ok(Val::Bool(a != b)),
-                    _ => Err(RuntimeError::invalid_binary_operation(bin.location)),
-                },
+        Ok(_) => {}
+        Err(error) if json_errors => {
+            let status = exit_code(error.code());
+            let source = source
+                .or_else(|| fs::read_to_string(&program.name).ok())
+                .or(program.source);
+            let error = match source {
+                Some(source) => error.with_source(&source),
+                None => error,
+            };
+            let mut json = serde_json::json!({
+                "code": error.code(),
+                "message": error.message(),
+                "start": error.location().start,
+                "end": error.location().end,
+            });
+            if let Some((line, column)) = error.position() {
+                json["line"] = line.into();
+                json["column"] = column.into();
             }
+            eprintln!("{json}");
+            process::exit(status);
         }
-
-        Term::If(i) => {
-            let location = i.condition.location().clone();
-            match eval(i.condition, scope)? {
-                Val::Bool(true) => eval(i.then, scope),
-                Val::Bool(false) => eval(i.otherwise, scope),
-                _ => Err(RuntimeError::new("condição inválida", location)),
+        Err(error) => {
+            let status = exit_code(error.code());
+            let source = source.or_else(|| fs::read_to_string(program.name).ok());
+            if let Some(source) = source {
+                let report = miette::Report::new(error).with_source_code(source);
+                print!("{:?}", report)
+            } else if let Some(source) = program.source {
+                println!("{}", error.with_source(&source));
+            } else {
+                println!("{}", error);
             }
+            process::exit(status);
         }
-
-        Term::Let(l) => {
-            let name = l.name.text;
-            scope.set(name, eval(l.value, scope)?);
-            eval(l.next, scope)
-        }
-
-        Term::Var(v) => match scope.get(&v.text) {
-            Some(val) => Ok(val.clone()),
-            None => Err(RuntimeError::unknow_identifier(v)),
-        },
-
-        Term::Function(fun) => Ok(Val::Closure {
-            fun: *fun,
-            env: scope.clone(),
-        }),
-
-        Term::Call(call) => match eval(call.callee, scope)? {
-            Val::Closure { fun, env } => {
-                if call.arguments.len() != fun.parameters.len() {
-                    return Err(RuntimeError::invalid_number_of_arguments(
-                        fun,
-                        call.location,
-                    ));
-                }
-
-                for (param, arg) in fun.parameters.into_iter().zip(call.arguments) {
-                    env.set(param.text, eval(arg, scope)?);
-                }
-
-                eval(fun.value, &env)
-            }
-            _ => Err(RuntimeError::new("não é uma função", call.location)),
-        },
     }
 }
 
-fn main() {
-    let program = match args().nth(1) {
-        Some(file) => fs::read_to_string(file).expect("Arquivo não encontrado"),
-        None => {
-            let mut buf = String::new();
-            stdin().lock().read_to_string(&mut buf).unwrap();
-            buf
-        }
-    };
+/// Whether there is nothing left to show of `val`, the value `interpreter`
+/// just evaluated: `print` or `printf` already showed it, and nothing is
+/// shown of a unit.
+fn echoed(interpreter: &Interpreter, val: &Val) -> bool {
+    interpreter.printed() || *val == Val::Unit
+}
 
-    let program = {
-        let mut deserializer = serde_json::Deserializer::from_str(&program);
-        deserializer.disable_recursion_limit();
-        let deserializer = serde_stacker::Deserializer::new(&mut deserializer);
-        File::deserialize(deserializer).expect("Programa inválido")
-    };
+/// The exit status for a runtime error with the given code:
+///
+/// - 2 for division by zero and integer overflow;
+/// - 3 for unknown identifiers, including uses before their definition;
+/// - 4 for calls with the wrong number of arguments;
+/// - 1 for type errors and everything else, invalid programs included.
+fn exit_code(code: &str) -> i32 {
+    match code {
+        "E_DIV_ZERO" | "E_OVERFLOW" => 2,
+        "E_UNKNOWN_IDENT" | "E_USE_BEFORE_DEF" => 3,
+        "E_ARITY" => 4,
+        _ => 1,
+    }
+}
 
-    let term = program.expression;
-    let scope = Scope::default();
-    if let Err(error) = eval(term, &scope) {
-        if let Ok(source) = fs::read_to_string(program.name) {
-            let report = miette::Report::new(error).with_source_code(source);
-            print!("{:?}", report)
-        } else {
-            println!("{}", error);
+fn from_json(json: &str, tag_field: &str) -> File {
+    match compile_with_tag(json, tag_field) {
+        Ok(program) => program.file,
+        Err(error) => {
+            println!("Programa inválido: {error}");
+            process::exit(1);
         }
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Reads rinha source line by line, evaluating each one in the same scope.
+///
+/// A line ending in `;` only introduces bindings, so its value isn't printed.
+fn run_repl(interpreter: &mut Interpreter) {
+    let interactive = stdin().is_terminal();
+    let scope = Scope::default();
+    let mut lines = stdin().lock().lines();
+
+    loop {
+        if interactive {
+            print!("> ");
+            stdout().flush().unwrap();
+        }
 
-    #[test]
-    fn scope_test() {
-        let s1 = Scope::default();
-        s1.set("a", Val::Int(1));
-        s1.set("b", Val::Int(2));
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
 
-        let s2 = s1.clone();
-        assert_eq!(s1.get("a"), Some(Val::Int(1)));
-        assert_eq!(s2.get("a"), Some(Val::Int(1)));
-        s2.set("a", Val::Int(2));
-        assert_eq!(s2.get("a"), Some(Val::Int(2)));
+        // The parser wants an expression after every `let`, so give it one.
+        let binding = line.ends_with(';');
+        let source = if binding {
+            format!("{line} 0")
+        } else {
+            line.to_owned()
+        };
 
-        let s3 = s2.clone();
-        assert_eq!(s3.get("a"), Some(Val::Int(2)));
-        assert_eq!(s3.get("b"), Some(Val::Int(2)));
+        let term = match rinha::parser::parse_or_report("repl", &expand_int_literals(&source)) {
+            Ok(ast) => {
+                let ast = serde_json::to_value(ast.expression).expect("AST inválida");
+                serde_json::from_value(ast).expect("AST inválida")
+            }
+            Err(error) => {
+                print!("{:?}", miette::Report::new(error));
+                continue;
+            }
+        };
+
+        match interpreter.eval(&term, &scope) {
+            Ok(_) if binding => {}
+            Ok(val) if echoed(interpreter, &val) => {}
+            Ok(val) => interpreter.format.write(&mut stdout(), &val).unwrap(),
+            Err(error) => {
+                let report = miette::Report::new(error).with_source_code(source);
+                print!("{:?}", report);
+            }
+        }
     }
 }
@@ -0,0 +1,338 @@
+use std::{collections::HashMap, rc::Rc};
+
+use num_bigint::BigInt;
+
+use crate::{Function, Piece, Scope, Symbol, Term, Val};
+
+/// A hashable snapshot of a [`Val`], used to key memoized calls.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    BigInt(BigInt),
+    Float(u64),
+    Bool(bool),
+    Str(String),
+    Tuple(Box<Key>, Box<Key>),
+    TupleN(Vec<Key>),
+    List(Vec<Key>),
+    Tagged(String, Box<Key>),
+    Unit,
+    /// The function being called, referenced from its own body.
+    Recursive,
+}
+
+impl Key {
+    fn new(val: &Val, fun: &Rc<Function>) -> Option<Self> {
+        match val {
+            Val::Int(i) => Some(Key::Int(*i)),
+            Val::BigInt(i) => Some(Key::BigInt(i.clone())),
+            Val::Float(n) => Some(Key::Float(n.to_bits())),
+            Val::Bool(b) => Some(Key::Bool(*b)),
+            Val::Str(s) => Some(Key::Str(s.clone())),
+            Val::Tuple((fst, snd)) => Some(Key::Tuple(
+                Box::new(Key::new(fst, fun)?),
+                Box::new(Key::new(snd, fun)?),
+            )),
+            Val::TupleN(items) => Some(Key::TupleN(
+                items
+                    .iter()
+                    .map(|item| Key::new(item, fun))
+                    .collect::<Option<_>>()?,
+            )),
+            Val::List(items) => Some(Key::List(
+                items
+                    .iter()
+                    .map(|item| Key::new(item, fun))
+                    .collect::<Option<_>>()?,
+            )),
+            Val::Tagged { tag, value } => {
+                Some(Key::Tagged(tag.clone(), Box::new(Key::new(value, fun)?)))
+            }
+            Val::Unit => Some(Key::Unit),
+            Val::Map(_) => None,
+            Val::Closure { fun: other, .. } if Rc::ptr_eq(fun, other) => Some(Key::Recursive),
+            Val::Closure { .. } => None,
+        }
+    }
+}
+
+/// Caches the results of function calls.
+///
+/// A call is only cached when every value it can observe, its arguments and
+/// the variables it captures, is a plain value. Functions are assumed to be
+/// pure, which is why this is opt-in.
+#[derive(Default)]
+pub struct Memo {
+    results: HashMap<CallKey, Val>,
+    captures: HashMap<FunKey, Rc<[Symbol]>>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct CallKey(FunKey, Vec<Key>);
+
+/// A function compared by identity. It holds on to the function, so its
+/// address can't be taken by another one, like the short lived functions
+/// partial application makes, while it is cached.
+#[derive(Clone)]
+struct FunKey(Rc<Function>);
+
+impl PartialEq for FunKey {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for FunKey {}
+
+impl std::hash::Hash for FunKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl Memo {
+    /// Builds the cache key for calling `fun` in `env`, which must already
+    /// have the arguments bound.
+    pub fn key(&mut self, fun: &Rc<Function>, env: &Scope) -> Option<CallKey> {
+        let captures = self
+            .captures
+            .entry(FunKey(fun.clone()))
+            .or_insert_with(|| free_variables(fun).into())
+            .clone();
+
+        let keys = captures
+            .iter()
+            .chain(fun.parameters.iter().map(|param| &param.text))
+            .map(|name| Key::new(&env.get(*name)?, fun))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(CallKey(FunKey(fun.clone()), keys))
+    }
+
+    pub fn get(&self, key: &CallKey) -> Option<Val> {
+        self.results.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: CallKey, val: Val) {
+        self.results.insert(key, val);
+    }
+}
+
+/// Names used by the function body that aren't bound inside of it.
+fn free_variables(fun: &Function) -> Vec<Symbol> {
+    let mut bound = fun.parameters.iter().map(|p| p.text).collect();
+    let mut free = Vec::new();
+    collect_free(&fun.value, &mut bound, &mut free);
+    free
+}
+
+fn collect_free(term: &Term, bound: &mut Vec<Symbol>, free: &mut Vec<Symbol>) {
+    match term {
+        Term::Str(t) => {
+            for piece in t.pieces().into_iter().flatten() {
+                if let Piece::Expr(term) = piece {
+                    collect_free(&term, bound, free);
+                }
+            }
+        }
+        Term::Int(_) | Term::Float(_) | Term::Bool(_) => {}
+        Term::Var(var) => {
+            if !bound.contains(&var.text) && !free.contains(&var.text) {
+                free.push(var.text);
+            }
+        }
+        Term::Print(t) => collect_free(&t.value, bound, free),
+        Term::Negate(t) => collect_free(&t.value, bound, free),
+        Term::Not(t) => collect_free(&t.value, bound, free),
+        Term::First(t) => collect_free(&t.value, bound, free),
+        Term::Second(t) => collect_free(&t.value, bound, free),
+        Term::Binary(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+        Term::Tuple(t) => {
+            collect_free(&t.first, bound, free);
+            collect_free(&t.second, bound, free);
+        }
+        Term::If(t) => {
+            collect_free(&t.condition, bound, free);
+            collect_free(&t.then, bound, free);
+            collect_free(&t.otherwise, bound, free);
+        }
+        Term::Call(t) => {
+            collect_free(&t.callee, bound, free);
+            for arg in &t.arguments {
+                collect_free(arg, bound, free);
+            }
+        }
+        Term::Let(t) => {
+            let len = bound.len();
+            t.name.names(bound);
+            collect_free(&t.value, bound, free);
+            collect_free(&t.next, bound, free);
+            bound.truncate(len);
+        }
+        Term::Function(t) => {
+            let len = bound.len();
+            bound.extend(t.parameters.iter().map(|p| p.text));
+            collect_free(&t.value, bound, free);
+            bound.truncate(len);
+        }
+        Term::ToStr(t) => collect_free(&t.value, bound, free),
+        Term::ToInt(t) => collect_free(&t.value, bound, free),
+        Term::Length(t) => collect_free(&t.value, bound, free),
+        Term::List(t) => {
+            for item in &t.items {
+                collect_free(item, bound, free);
+            }
+        }
+        Term::Index(t) => {
+            collect_free(&t.target, bound, free);
+            collect_free(&t.index, bound, free);
+        }
+        Term::Cons(t) => {
+            collect_free(&t.head, bound, free);
+            collect_free(&t.tail, bound, free);
+        }
+        Term::Head(t) => collect_free(&t.value, bound, free),
+        Term::Tail(t) => collect_free(&t.value, bound, free),
+        Term::CharAt(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.index, bound, free);
+        }
+        Term::Substr(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.start, bound, free);
+            collect_free(&t.len, bound, free);
+        }
+        Term::ReadLine(_) => {}
+        Term::Min(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+        Term::Max(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+        Term::Abs(t) => collect_free(&t.value, bound, free),
+        Term::GetEnv(t) => collect_free(&t.value, bound, free),
+        Term::Assert(t) => collect_free(&t.value, bound, free),
+        Term::Map(_) => {}
+        Term::MapInsert(t) => {
+            collect_free(&t.map, bound, free);
+            collect_free(&t.key, bound, free);
+            collect_free(&t.value, bound, free);
+        }
+        Term::MapGet(t) => {
+            collect_free(&t.map, bound, free);
+            collect_free(&t.key, bound, free);
+        }
+        Term::While(t) => {
+            collect_free(&t.condition, bound, free);
+            collect_free(&t.body, bound, free);
+        }
+        Term::Assign(t) => {
+            if !bound.contains(&t.name.text) && !free.contains(&t.name.text) {
+                free.push(t.name.text);
+            }
+            collect_free(&t.value, bound, free);
+        }
+        Term::Tag(t) => {
+            if let Some(value) = &t.value {
+                collect_free(value, bound, free);
+            }
+        }
+        Term::MatchTag(t) => {
+            collect_free(&t.value, bound, free);
+            for arm in &t.arms {
+                let len = bound.len();
+                bound.extend(arm.binding.as_ref().map(|p| p.text));
+                collect_free(&arm.then, bound, free);
+                bound.truncate(len);
+            }
+        }
+        Term::Seq(t) => {
+            collect_free(&t.first, bound, free);
+            collect_free(&t.second, bound, free);
+        }
+        Term::IsInt(t) => collect_free(&t.value, bound, free),
+        Term::IsStr(t) => collect_free(&t.value, bound, free),
+        Term::IsBool(t) => collect_free(&t.value, bound, free),
+        Term::IsTuple(t) => collect_free(&t.value, bound, free),
+        Term::IsFunction(t) => collect_free(&t.value, bound, free),
+        Term::Fold(t) => {
+            collect_free(&t.list, bound, free);
+            collect_free(&t.init, bound, free);
+            collect_free(&t.func, bound, free);
+        }
+        Term::MapList(t) => {
+            collect_free(&t.list, bound, free);
+            collect_free(&t.func, bound, free);
+        }
+        Term::Range(t) => {
+            collect_free(&t.start, bound, free);
+            collect_free(&t.end, bound, free);
+        }
+        Term::TupleN(t) => {
+            for item in &t.items {
+                collect_free(item, bound, free);
+            }
+        }
+        Term::Nth(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.index, bound, free);
+        }
+        Term::PrintF(t) => {
+            collect_free(&t.template, bound, free);
+            collect_free(&t.args, bound, free);
+        }
+        Term::Upper(t) => collect_free(&t.value, bound, free),
+        Term::Lower(t) => collect_free(&t.value, bound, free),
+        Term::Split(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.sep, bound, free);
+        }
+        Term::Contains(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.pattern, bound, free);
+        }
+        Term::StartsWith(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.pattern, bound, free);
+        }
+        Term::EndsWith(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.pattern, bound, free);
+        }
+        Term::Replace(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.from, bound, free);
+            collect_free(&t.to, bound, free);
+        }
+        Term::Trim(t) => collect_free(&t.value, bound, free),
+        Term::Repeat(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.count, bound, free);
+        }
+        Term::Gensym(_) => {}
+        Term::Rand(t) => collect_free(&t.max, bound, free),
+        Term::DivMod(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+        Term::Signum(t) => collect_free(&t.value, bound, free),
+        Term::Clamp(t) => {
+            collect_free(&t.value, bound, free);
+            collect_free(&t.lo, bound, free);
+            collect_free(&t.hi, bound, free);
+        }
+        Term::Gcd(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+        Term::Lcm(t) => {
+            collect_free(&t.lhs, bound, free);
+            collect_free(&t.rhs, bound, free);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::io;
+
+use crate::{Bool, If, Int, Interpreter, Scope, Str, Term, Val};
+
+/// Replaces the binary operations between literals with their results, all
+/// the way up, so `2 * 3 + 1` becomes `7`, and the `if`s whose condition
+/// ends up a literal with the branch it picks.
+///
+/// Operations that would fail, like `1 / 0` or an overflow, are left as they
+/// are, for the interpreter to report. This folds with the default integer
+/// arithmetic; programs run with `--int-width` or `--bignum` should be
+/// folded with [`Interpreter::fold_constants`] instead.
+pub fn fold_constants(term: Term) -> Term {
+    Interpreter::default().fold_constants(term)
+}
+
+impl Interpreter<'_> {
+    /// Like [`fold_constants`], with integers wrapping and growing as they do
+    /// when this interpreter runs the program.
+    pub fn fold_constants(&self, mut term: Term) -> Term {
+        let mut folder = Interpreter::new(io::sink());
+        folder.int_width = self.int_width;
+        folder.bignum = self.bignum;
+        fold(&mut term, &mut folder);
+        term
+    }
+}
+
+fn fold(term: &mut Term, folder: &mut Interpreter) {
+    for child in term.children_mut() {
+        fold(child, folder);
+    }
+
+    *term = match term {
+        Term::Binary(bin) if is_literal(&bin.lhs) && is_literal(&bin.rhs) => {
+            let location = bin.location.clone();
+            match folder.eval(term, &Scope::default()) {
+                Ok(Val::Int(value)) => Term::Int(Int { value, location }),
+                Ok(Val::Bool(value)) => Term::Bool(Bool { value, location }),
+                // A concatenation could make up an interpolation, like "$" + "{a}".
+                Ok(Val::Str(value)) if !value.contains("${") => Term::Str(Str { value, location }),
+                _ => return,
+            }
+        }
+
+        Term::If(t) => {
+            let If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } = &mut **t;
+            let branch = match condition {
+                Term::Bool(Bool { value: true, .. }) => then,
+                Term::Bool(Bool { value: false, .. }) => otherwise,
+                _ => return,
+            };
+            std::mem::replace(branch, condition.clone())
+        }
+
+        _ => return,
+    };
+}
+
+fn is_literal(term: &Term) -> bool {
+    match term {
+        Term::Int(_) | Term::Bool(_) => true,
+        Term::Str(s) => !s.value.contains("${"),
+        _ => false,
+    }
+}
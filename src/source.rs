@@ -0,0 +1,68 @@
+/// Rewrites the hexadecimal (`0xFF`) and binary (`0b1010`) integer literals
+/// of rinha source, which the parser doesn't know, as decimal ones.
+///
+/// Each is padded with spaces to its original length, so every location in
+/// the parsed program still points to the same place of `source`. Strings and
+/// comments are left alone, and so are literals that don't fit in an `i64`.
+pub fn expand_int_literals(source: &str) -> String {
+    let mut expanded = String::with_capacity(source.len());
+    let mut rest = source;
+    let mut previous = None;
+
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '"' => string_len(rest),
+            '/' if rest.starts_with("//") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |i| i + 4),
+            '0' if !previous.is_some_and(is_identifier) => match radix_literal(rest) {
+                Some((len, value)) => {
+                    expanded.push_str(&format!("{value:>len$}"));
+                    rest = &rest[len..];
+                    previous = Some('0');
+                    continue;
+                }
+                None => 1,
+            },
+            c => c.len_utf8(),
+        };
+
+        expanded.push_str(&rest[..skip]);
+        previous = rest[..skip].chars().next_back();
+        rest = &rest[skip..];
+    }
+
+    expanded
+}
+
+/// The length of the string literal `source` starts with, quotes included.
+fn string_len(source: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in source.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    source.len()
+}
+
+/// The length and value of the hexadecimal or binary literal `source`
+/// starts with, if any.
+fn radix_literal(source: &str) -> Option<(usize, i64)> {
+    let radix = match source.get(..2)? {
+        "0x" | "0X" => 16,
+        "0b" | "0B" => 2,
+        _ => return None,
+    };
+    let len = source[2..]
+        .find(|c: char| !is_identifier(c))
+        .map_or(source.len(), |i| i + 2);
+    let value = i64::from_str_radix(&source[2..len], radix).ok()?;
+    Some((len, value))
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
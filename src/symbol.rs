@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    sync::{LazyLock, Mutex},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An interned name.
+///
+/// Variables are looked up on every access, so scopes are keyed by these
+/// instead of strings: comparing and hashing them never touches the name.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+// Names are never freed: a program only has so many of them.
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(&symbol) = interner.symbols.get(name) {
+            return symbol;
+        }
+
+        let symbol = Symbol(interner.names.len() as u32);
+        let name: &'static str = Box::leak(name.into());
+        interner.names.push(name);
+        interner.symbols.insert(name, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().names[self.0 as usize]
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use rinha_compiladores::{File, Interpreter, Val};
+
+/// Counts every allocation, for this test binary alone.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// `fib`, with a branch no call takes holding `dead`.
+fn fib(dead: &str) -> File {
+    let source = format!(
+        "
+        let fib = fn (n) => {{
+          if (n < 0) {{
+            {dead}
+          }} else {{
+            if (n < 2) {{ n }} else {{ fib(n - 1) + fib(n - 2) }}
+          }}
+        }};
+        fib(20)
+        "
+    );
+    let ast = rinha::parser::parse_or_report("test.rinha", &source).unwrap();
+    serde_json::from_value(serde_json::to_value(ast).unwrap()).unwrap()
+}
+
+fn allocations(file: &File) -> usize {
+    let mut interpreter = Interpreter::new(std::io::sink());
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    assert_eq!(interpreter.run(file).unwrap(), Val::Int(6765));
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn test_calls_share_the_function() {
+    // Calls don't copy the function, so how big it is doesn't change how
+    // much they allocate.
+    // Unoptimized builds need a larger stack than the test's for the calls.
+    let (small, big) = thread::Builder::new()
+        .stack_size(256 << 20)
+        .spawn(|| {
+            let small = fib("0");
+            let big = fib(&vec!["1"; 1000].join(" + "));
+            (allocations(&small), allocations(&big))
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(small, big);
+}
@@ -1,23 +1,90 @@
-use assert_cmd::Command;
+use assert_cmd::{assert::Assert, Command};
 use indoc::indoc;
+use serde_json::{json, Value};
 
 macro_rules! rinha {
     ($expr:expr) => {{
-        let ast = rinha::parser::parse_or_report("test.rinha", indoc! { $expr }).unwrap();
-        let ast = serde_json::to_string_pretty(&ast).unwrap();
+        let cmd = run(indoc! { $expr }).success();
+        let out = cmd.get_output();
+        let output = std::str::from_utf8(&out.stdout).unwrap();
+        output.trim_end().to_owned()
+    }};
+}
 
-        let cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))
-            .unwrap()
-            .write_stdin(ast)
-            .assert()
-            .success();
+macro_rules! rinha_err {
+    ($expr:expr) => {{
+        let cmd = run(indoc! { $expr }).failure();
+        let out = cmd.get_output();
+        let output = std::str::from_utf8(&out.stdout).unwrap();
+        output.trim_end().to_owned()
+    }};
+}
+
+macro_rules! ast {
+    ($($json:tt)+) => {{
+        let cmd = run_ast(json!($($json)+)).success();
+        let out = cmd.get_output();
+        let output = std::str::from_utf8(&out.stdout).unwrap();
+        output.trim_end().to_owned()
+    }};
+}
 
+macro_rules! ast_err {
+    ($($json:tt)+) => {{
+        let cmd = run_ast(json!($($json)+)).failure();
         let out = cmd.get_output();
         let output = std::str::from_utf8(&out.stdout).unwrap();
         output.trim_end().to_owned()
     }};
 }
 
+fn run(source: &str) -> Assert {
+    command().write_stdin(parse(source)).assert()
+}
+
+fn command() -> Command {
+    Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap()
+}
+
+/// Parses a rinha program into the JSON AST the interpreter expects.
+fn parse(source: &str) -> String {
+    let ast = rinha::parser::parse_or_report("test.rinha", source).unwrap();
+    serde_json::to_string_pretty(&ast).unwrap()
+}
+
+/// Runs a hand built expression, for terms the rinha parser doesn't know about.
+fn run_ast(expression: Value) -> Assert {
+    let ast = json!({ "name": "test.rinha", "expression": expression });
+
+    command().write_stdin(ast.to_string()).assert()
+}
+
+/// Parses a rinha expression into its JSON AST.
+fn term(source: &str) -> Value {
+    let ast = rinha::parser::parse_or_report("test.rinha", source).unwrap();
+    serde_json::to_value(ast.expression).unwrap()
+}
+
+fn location() -> Value {
+    json!({ "start": 0, "end": 0 })
+}
+
+fn print(value: Value) -> Value {
+    unary("Print", value)
+}
+
+fn unary(kind: &str, value: Value) -> Value {
+    json!({ "kind": kind, "value": value, "location": location() })
+}
+
+fn binary(op: &str, lhs: Value, rhs: Value) -> Value {
+    json!({ "kind": "Binary", "op": op, "lhs": lhs, "rhs": rhs, "location": location() })
+}
+
+fn float(value: f64) -> Value {
+    json!({ "kind": "Float", "value": value, "location": location() })
+}
+
 #[test]
 fn test_print() {
     assert_eq!(rinha!(r#"print("hello")"#), "hello");
@@ -32,12 +99,50 @@ fn test_print() {
             };
             print(f)
         "},
-        "<#closure>"
+        "<#closure f/0>"
     );
+    assert_eq!(rinha!("print(fn (a, b) => { a + b })"), "<#closure/2>");
     assert_eq!(rinha!("print(print(1))"), "1\n1");
     assert_eq!(rinha!("print(\"1 + 2 = \" + 1 + 2)"), "1 + 2 = 3");
 }
 
+#[test]
+fn test_print_returns_unit() {
+    let program = parse("let _ = print(print(1)); print((print(2), 3))");
+
+    command()
+        .write_stdin(program.clone())
+        .assert()
+        .success()
+        .stdout("1\n1\n2\n(2, 3)\n");
+
+    command()
+        .arg("--print-returns-unit")
+        .write_stdin(program)
+        .assert()
+        .success()
+        .stdout("1\n2\n(, 3)\n");
+}
+
+#[test]
+fn test_print_named_function() {
+    assert_eq!(
+        rinha! {"
+            let fib = fn (n) => { n };
+            let alias = fib;
+            print((fib, alias))
+        "},
+        "(<#closure fib/1>, <#closure fib/1>)"
+    );
+    assert_eq!(
+        rinha! {"
+            let add = fn (a) => { fn (b) => { a + b } };
+            print(add(1))
+        "},
+        "<#closure/1>"
+    );
+}
+
 #[test]
 fn test_binary_operations() {
     assert_eq!(rinha!("print(2 + 2)"), "4");
@@ -55,6 +160,375 @@ fn test_binary_operations() {
     assert_eq!(rinha!("print(true && false)"), "false");
     assert_eq!(rinha!("print(true || false)"), "true");
     assert_eq!(rinha!("print(false || false)"), "false");
+    assert_eq!(
+        rinha_err! {"
+            let f = fn () => { 1 };
+            print(f * 2)
+        "},
+        "operação inválida entre Closure e Int"
+    );
+}
+
+#[test]
+fn test_negate() {
+    assert_eq!(ast!(print(unary("Negate", term("1 + 2")))), "-3");
+    assert_eq!(ast!(print(unary("Negate", term("0 - 5")))), "5");
+    assert_eq!(
+        ast_err!(print(unary("Negate", term("true")))),
+        "operação inválida"
+    );
+}
+
+#[test]
+fn test_not() {
+    assert_eq!(ast!(print(unary("Not", term("1 == 2")))), "true");
+    assert_eq!(ast!(print(unary("Not", term("true")))), "false");
+    assert_eq!(
+        ast_err!(print(unary("Not", term("5")))),
+        "não é um booleano"
+    );
+}
+
+#[test]
+fn test_to_str() {
+    assert_eq!(ast!(print(unary("ToStr", term("40 + 2")))), "42");
+    assert_eq!(ast!(print(unary("ToStr", term("(1, true)")))), "(1, true)");
+}
+
+#[test]
+fn test_to_int() {
+    let to_int = |value| {
+        json!({
+            "kind": "Binary",
+            "op": "Add",
+            "lhs": unary("ToInt", value),
+            "rhs": term("1"),
+            "location": location(),
+        })
+    };
+
+    assert_eq!(ast!(print(to_int(term(r#""42""#)))), "43");
+    assert_eq!(
+        ast_err!(print(to_int(term(r#""abc""#)))),
+        "\"abc\" não é um número inteiro"
+    );
+    assert_eq!(ast_err!(print(to_int(term("42")))), "não é uma string");
+}
+
+#[test]
+fn test_length() {
+    assert_eq!(ast!(print(unary("Length", term(r#""héllo""#)))), "5");
+    assert_eq!(ast!(print(unary("Length", term(r#""""#)))), "0");
+    assert_eq!(
+        ast_err!(print(unary("Length", term("12345")))),
+        "não é uma string"
+    );
+}
+
+#[test]
+fn test_float() {
+    assert_eq!(ast!(print(binary("Add", float(1.5), term("2")))), "3.5");
+    assert_eq!(ast!(print(binary("Div", float(7.0), term("2")))), "3.5");
+    assert_eq!(ast!(print(binary("Mul", float(1.5), float(2.0)))), "3");
+    assert_eq!(ast!(print(binary("Sub", term("1"), float(0.25)))), "0.75");
+    assert_eq!(ast!(print(binary("Lt", float(1.5), term("2")))), "true");
+    assert_eq!(ast!(print(binary("Gte", term("1"), float(1.5)))), "false");
+    assert_eq!(ast!(print(binary("Eq", float(2.0), term("2")))), "true");
+    assert_eq!(ast!(print(unary("Negate", float(2.5)))), "-2.5");
+    assert_eq!(
+        ast_err!(print(binary("Add", float(1.5), term("true")))),
+        "operação inválida entre Float e Bool"
+    );
+}
+
+#[test]
+fn test_pow() {
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            binary("Pow", term("2"), term("10")),
+            term("1024")
+        ))),
+        "true"
+    );
+    assert_eq!(ast!(print(binary("Pow", term("5"), term("0")))), "1");
+    assert_eq!(ast!(print(binary("Pow", float(2.0), term("0 - 1")))), "0.5");
+    assert_eq!(
+        ast_err!(print(binary("Pow", term("2"), term("0 - 1")))),
+        "expoente negativo"
+    );
+    assert_eq!(
+        ast_err!(print(binary("Pow", term("2"), term("64")))),
+        "estouro de inteiro"
+    );
+    assert_eq!(
+        ast_err!(print(binary("Pow", term("true"), term("2")))),
+        "operação inválida entre Bool e Int"
+    );
+}
+
+#[test]
+fn test_string_comparison() {
+    assert_eq!(rinha!(r#"print("abc" < "abd")"#), "true");
+    assert_eq!(rinha!(r#"print("b" > "a")"#), "true");
+    assert_eq!(
+        rinha!(r#"print(("a" >= "a", "a" <= "B"))"#),
+        "(true, false)"
+    );
+    assert_eq!(
+        rinha_err!(r#"print("1" < 2)"#),
+        "operação inválida entre Str e Int"
+    );
+}
+
+#[test]
+fn test_tuple_comparison() {
+    // Tuples can't be operands in rinha source, so they are bound first.
+    assert_eq!(
+        rinha!("let a = (1, 2); let b = (1, 3); print(a < b)"),
+        "true"
+    );
+    assert_eq!(
+        rinha!("let a = (2, 0); let b = (1, 9); print(a < b)"),
+        "false"
+    );
+    assert_eq!(rinha!("let a = (1, (2, 3)); print(a >= a)"), "true");
+    assert_eq!(
+        rinha!(r#"let a = ("a", 2); let b = ("b", 1); print(a > b)"#),
+        "false"
+    );
+    assert_eq!(
+        rinha_err!(r#"let a = (1, 2); let b = ("a", 2); print(a < b)"#),
+        "operação inválida entre Tuple e Tuple"
+    );
+    assert_eq!(
+        rinha_err!("let a = (1, fn () => { 1 }); print(a <= a)"),
+        "operação inválida entre Tuple e Tuple"
+    );
+}
+
+#[test]
+fn test_closure_equality() {
+    assert_eq!(rinha!("let f = fn () => { 1 }; print(f == f)"), "true");
+    assert_eq!(
+        rinha!("let f = fn () => { 1 }; let g = f; print(f == g)"),
+        "true"
+    );
+    assert_eq!(
+        rinha!("let f = fn () => { 1 }; let g = fn () => { 1 }; print(f != g)"),
+        "true"
+    );
+    assert_eq!(
+        rinha!("let make = fn () => { fn () => { 1 } }; print(make() == make())"),
+        "false"
+    );
+}
+
+#[test]
+fn test_string_concatenation() {
+    assert_eq!(rinha!(r#"print("a" + 1)"#), "a1");
+    assert_eq!(rinha!(r#"print(1 + "a")"#), "1a");
+    assert_eq!(rinha!(r#"print("a" + true)"#), "atrue");
+    assert_eq!(rinha!(r#"print("a" + "b")"#), "ab");
+    assert_eq!(
+        rinha_err!("print(true + false)"),
+        "operação inválida entre Bool e Bool"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let a = (1, 2);
+            print(a + 3)
+        "},
+        "operação inválida entre Tuple e Int"
+    );
+}
+
+#[test]
+fn test_tuple_equality() {
+    assert_eq!(
+        rinha! {"
+            let a = (1, 2);
+            let b = (1, 2);
+            print((a == b, a != b))
+        "},
+        "(true, false)"
+    );
+    assert_eq!(
+        rinha! {"
+            let a = ((1, 2), 3);
+            let b = ((1, 2), 3);
+            let c = ((1, 3), 3);
+            print(((a == b, a != b), (a == c, a != c)))
+        "},
+        "((true, false), (false, true))"
+    );
+}
+
+#[test]
+fn test_type_errors() {
+    assert_eq!(
+        rinha_err!("print(first(1))"),
+        "esperava tupla, encontrou Int"
+    );
+    assert_eq!(
+        rinha_err!(r#"print(second("a"))"#),
+        "esperava tupla, encontrou Str"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let x = 1;
+            print(x(2))
+        "},
+        "esperava função, encontrou Int"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let f = fn () => { 1 };
+            if (f) { 2 } else { 3 }
+        "},
+        "esperava booleano, encontrou Closure"
+    );
+}
+
+#[test]
+fn test_short_circuit() {
+    assert_eq!(rinha!(r#"print(false && print("x"))"#), "false");
+    assert_eq!(rinha!(r#"print(true || print("x"))"#), "true");
+    assert_eq!(rinha!("print(true && print(false))"), "false\nfalse");
+    assert_eq!(rinha!("print(false || print(true))"), "true\ntrue");
+    assert_eq!(
+        rinha_err!("print(1 && true)"),
+        "operação inválida entre Int e Bool"
+    );
+}
+
+#[test]
+fn test_integer_overflow() {
+    // Literals must fit in an i32, so 2^31 has to be computed.
+    assert_eq!(
+        rinha_err! {"
+            let p = 2147483647 + 1;
+            print(p * p * 2)
+        "},
+        "estouro de inteiro"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let q = (2147483647 + 1) * (2147483647 + 1);
+            print(q + q)
+        "},
+        "estouro de inteiro"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let q = (2147483647 + 1) * (2147483647 + 1);
+            print(((0 - q) - q) - 1)
+        "},
+        "estouro de inteiro"
+    );
+}
+
+#[test]
+fn test_division_overflow() {
+    assert_eq!(
+        rinha_err! {"
+            let q = (2147483647 + 1) * (2147483647 + 1);
+            let min = (0 - q) - q;
+            print(min / (0 - 1))
+        "},
+        "estouro de inteiro"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let q = (2147483647 + 1) * (2147483647 + 1);
+            let min = (0 - q) - q;
+            print(min % (0 - 1))
+        "},
+        "estouro de inteiro"
+    );
+}
+
+#[test]
+fn test_large_integers() {
+    assert_eq!(rinha!("print(2 * 2000000000)"), "4000000000");
+    assert_eq!(
+        rinha! {"
+            let fib = fn (n, a, b) => {
+              if (n == 0) {
+                a
+              } else {
+                fib(n - 1, b, a + b)
+              }
+            };
+            print((fib(46, 0, 1), fib(90, 0, 1)))
+        "},
+        "(1836311903, 2880067194370816120)"
+    );
+}
+
+#[test]
+fn test_bignum() {
+    let program = parse(indoc! {"
+        let fact = fn (n) => {
+          if (n == 0) {
+            1
+          } else {
+            n * fact(n - 1)
+          }
+        };
+        let big = fact(30);
+        let _ = print(big);
+        let _ = print((big > fact(20), big / fact(28)));
+        print((big - big) + 1)
+    "});
+
+    command()
+        .write_stdin(program.clone())
+        .assert()
+        .failure()
+        .stdout("estouro de inteiro\n");
+
+    command()
+        .arg("--bignum")
+        .write_stdin(program)
+        .assert()
+        .success()
+        .stdout("265252859812191058636308480000000\n(true, 870)\n1\n");
+}
+
+#[test]
+fn test_int_width() {
+    command()
+        .arg("--int-width=8")
+        .write_stdin(parse("print((200 + 100, (100 + 100, (0 - 1, 16 * 16))))"))
+        .assert()
+        .success()
+        .stdout("(44, (-56, (-1, 0)))\n");
+
+    let int = |n: i64| json!({ "kind": "Int", "value": n, "location": location() });
+    let program =
+        json!({ "name": "test.rinha", "expression": print(binary("Shl", int(1), int(7))) });
+    command()
+        .arg("--int-width=8")
+        .write_stdin(program.to_string())
+        .assert()
+        .success()
+        .stdout("-128\n");
+
+    let program =
+        json!({ "name": "test.rinha", "expression": print(binary("Add", int(i64::MAX), int(1))) });
+    command()
+        .arg("--int-width=64")
+        .write_stdin(program.to_string())
+        .assert()
+        .success()
+        .stdout("-9223372036854775808\n");
+}
+
+#[test]
+fn test_division_by_zero() {
+    assert_eq!(rinha_err!("print(10 / 0)"), "divisão por zero");
+    assert_eq!(rinha_err!("print(10 % 0)"), "divisão por zero");
 }
 
 #[test]
@@ -75,6 +549,24 @@ fn test_closure() {
     );
 }
 
+#[test]
+fn test_invalid_number_of_arguments() {
+    assert_eq!(
+        rinha_err! {"
+            let add = fn (a, b) => { a + b };
+            print(add(1))
+        "},
+        "esperava 2 argumentos, recebeu 1"
+    );
+    assert_eq!(
+        rinha_err! {"
+            let inc = fn (a) => { a + 1 };
+            print(inc(1, 2))
+        "},
+        "esperava 1 argumento, recebeu 2"
+    );
+}
+
 #[test]
 fn test_fibonacci() {
     assert_eq!(
@@ -93,17 +585,1617 @@ fn test_fibonacci() {
 }
 
 #[test]
-fn test_currying() {
+fn test_recursive_let() {
     assert_eq!(
         rinha! {r#"
-            let add = fn (a) => {
-              fn (b) => {
-                a + b
+            let isEven = fn (n) => {
+              if (n == 0) {
+                true
+              } else {
+                isOdd(n - 1)
               }
             };
-            let addOne = add(1);
-            print(addOne(2))
+            let isOdd = fn (n) => {
+              if (n == 0) {
+                false
+              } else {
+                isEven(n - 1)
+              }
+            };
+            print((isEven(10), isEven(7)))
+        "#},
+        "(true, false)"
+    );
+    assert_eq!(
+        rinha! {r#"
+            let count = fn (n) => {
+              if (n == 0) {
+                0
+              } else {
+                1 + count(n - 1)
+              }
+            };
+            let f = count;
+            let count = fn (n) => {
+              42
+            };
+            print(f(3))
         "#},
         "3"
     );
 }
+
+#[test]
+fn test_use_before_definition() {
+    assert_eq!(
+        rinha_err! {"
+            let x = x + 1;
+            print(x)
+        "},
+        "uso de 'x' antes de sua definição"
+    );
+    assert_eq!(rinha_err!("print(y)"), "identificador não encontrado");
+}
+
+#[test]
+fn test_closure_snapshot() {
+    assert_eq!(
+        rinha_err! {"
+            let peek = fn () => { sneaky };
+            let sneaky = 1;
+            print(peek())
+        "},
+        "identificador não encontrado"
+    );
+    assert_eq!(
+        rinha! {"
+            let x = 1;
+            let f = fn () => { x };
+            let x = 2;
+            print(f() + x)
+        "},
+        "3"
+    );
+    // Functions bound by later lets are the exception, so they can call
+    // each other.
+    assert_eq!(
+        rinha! {"
+            let ping = fn (n) => { if (n == 0) { 0 } else { pong(n - 1) } };
+            let pong = fn (n) => { if (n == 0) { 1 } else { ping(n - 1) } };
+            print((ping(4), ping(3)))
+        "},
+        "(0, 1)"
+    );
+}
+
+#[test]
+fn test_tail_call() {
+    assert_eq!(
+        rinha! {r#"
+            let count = fn (n, acc) => {
+              if (n == 0) {
+                acc
+              } else {
+                count(n - 1, acc + 2)
+              }
+            };
+            print(count(1000000, 0))
+        "#},
+        "2000000"
+    );
+}
+
+#[test]
+fn test_stack_size() {
+    let program = parse(indoc! {"
+        let sum = fn (n) => {
+          if (n == 0) {
+            0
+          } else {
+            n + sum(n - 1)
+          }
+        };
+        print(sum(1000))
+    "});
+
+    command()
+        .env("RINHA_STACK_MB", "1")
+        .write_stdin(program.clone())
+        .assert()
+        .failure();
+
+    command()
+        .write_stdin(program.clone())
+        .assert()
+        .success()
+        .stdout("500500\n");
+
+    for invalid in ["muito", "18446744073709551615"] {
+        command()
+            .env("RINHA_STACK_MB", invalid)
+            .write_stdin(program.clone())
+            .assert()
+            .code(1)
+            .stderr("RINHA_STACK_MB inválido\n");
+    }
+}
+
+#[test]
+fn test_recursion_limit() {
+    let program = parse(indoc! {"
+        let loop = fn () => {
+          loop()
+        };
+        loop()
+    "});
+
+    command()
+        .env("RINHA_MAX_STEPS", "1000")
+        .write_stdin(program)
+        .assert()
+        .failure()
+        .stdout("limite de recursão excedido\n");
+}
+
+#[test]
+fn test_timeout() {
+    let program = parse(indoc! {"
+        let loop = fn () => {
+          loop()
+        };
+        loop()
+    "});
+
+    command()
+        .arg("--timeout=1")
+        .env("RINHA_MAX_STEPS", "18446744073709551615")
+        .write_stdin(program)
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stdout("tempo limite excedido\n");
+}
+
+#[test]
+fn test_memoize() {
+    let fib = |n| {
+        parse(&format!(
+            "
+            let fib = fn (n) => {{
+              if (n < 2) {{
+                n
+              }} else {{
+                fib(n - 1) + fib(n - 2)
+              }}
+            }};
+            print(fib({n}))
+            "
+        ))
+    };
+
+    command()
+        .write_stdin(fib(20))
+        .assert()
+        .success()
+        .stdout("6765\n");
+
+    command()
+        .arg("--memoize")
+        .write_stdin(fib(20))
+        .assert()
+        .success()
+        .stdout("6765\n");
+
+    command()
+        .arg("--memoize")
+        .timeout(std::time::Duration::from_secs(5))
+        .write_stdin(fib(35))
+        .assert()
+        .success()
+        .stdout("9227465\n");
+}
+
+#[test]
+fn test_memoize_captured_variables() {
+    let program = parse(indoc! {"
+        let add = fn (a) => {
+          fn (b) => {
+            a + b
+          }
+        };
+        let addOne = add(1);
+        let addTwo = add(2);
+        print((addOne(1), addTwo(1)))
+    "});
+
+    command()
+        .arg("--memoize")
+        .write_stdin(program)
+        .assert()
+        .success()
+        .stdout("(2, 3)\n");
+}
+
+#[test]
+fn test_memoize_partial_application() {
+    // Each partial application makes a function that is soon dropped, and
+    // whose memoized calls mustn't be taken for those of the next one.
+    let program = parse(indoc! {"
+        let add = fn (a, b) => { a + b };
+        let mul = fn (a, b) => { a * b };
+        let sub = fn (a, b) => { a - b };
+        let x = add(3)(2);
+        let y = mul(3)(2);
+        let z = sub(3)(2);
+        let w = add(2)(3);
+        let v = mul(2)(3);
+        print((x, (y, (z, (w, v)))))
+    "});
+
+    command()
+        .arg("--memoize")
+        .arg("--auto-curry")
+        .write_stdin(program)
+        .assert()
+        .success()
+        .stdout("(5, (6, (1, (5, 6))))\n");
+}
+
+#[test]
+fn test_json_format() {
+    let json = |source| {
+        let cmd = command()
+            .arg("--format=json")
+            .write_stdin(parse(source))
+            .assert()
+            .success();
+        String::from_utf8(cmd.get_output().stdout.clone()).unwrap()
+    };
+
+    assert_eq!(json("(1, false)"), "[1,false]\n");
+    assert_eq!(json(r#"("a", (1, true))"#), "[\"a\",[1,true]]\n");
+    assert_eq!(json("fn (x) => { x }"), "{\"closure\":true}\n");
+    assert_eq!(json(r#"print("a")"#), "\"a\"\n");
+    assert_eq!(json(r#"let _ = print("a"); 1"#), "\"a\"\n1\n");
+    assert_eq!(json("let f = fn (x) => { x + 1 }; print(f(1))"), "2\n");
+}
+
+#[test]
+fn test_currying() {
+    assert_eq!(
+        rinha! {r#"
+            let add = fn (a) => {
+              fn (b) => {
+                a + b
+              }
+            };
+            let addOne = add(1);
+            print(addOne(2))
+        "#},
+        "3"
+    );
+}
+
+#[test]
+fn test_auto_curry() {
+    let program = parse(indoc! {"
+        let add = fn (a, b) => { a + b };
+        let addOne = add(1);
+        print((add(1)(2), addOne(41)))
+    "});
+
+    command()
+        .arg("--auto-curry")
+        .write_stdin(program.clone())
+        .assert()
+        .success()
+        .stdout("(3, 42)\n");
+
+    command()
+        .write_stdin(program)
+        .assert()
+        .failure()
+        .stdout("esperava 2 argumentos, recebeu 1\n");
+
+    command()
+        .arg("--auto-curry")
+        .write_stdin(parse(
+            "let add = fn (a, b) => { a + b }; print(add(1, 2, 3))",
+        ))
+        .assert()
+        .failure()
+        .stdout("esperava 2 argumentos, recebeu 3\n");
+}
+
+#[test]
+fn test_repl() {
+    command()
+        .arg("--repl")
+        .write_stdin("let x = 5;\nx + 1\n")
+        .assert()
+        .success()
+        .stdout("6\n");
+
+    // Errors are reported without leaving the loop.
+    let cmd = command()
+        .arg("--repl")
+        .write_stdin("print(y)\nlet y = 1;\ny\n")
+        .assert()
+        .success();
+    let output = std::str::from_utf8(&cmd.get_output().stdout).unwrap();
+    assert!(output.contains("identificador não encontrado"));
+    assert!(output.ends_with("1\n"));
+
+    // What is printed isn't echoed again.
+    command()
+        .arg("--repl")
+        .write_stdin("print(1)\n2\n")
+        .assert()
+        .success()
+        .stdout("1\n2\n");
+}
+
+#[test]
+fn test_auto_print() {
+    let run = |source| {
+        command()
+            .arg("--auto-print")
+            .write_stdin(parse(source))
+            .assert()
+            .success()
+    };
+
+    run("1 + 2").stdout("3\n");
+    run(r#""a""#).stdout("a\n");
+    run("print(3)").stdout("3\n");
+    run("let x = print(1); x + 1").stdout("1\n2\n");
+    run("let x = 1; print(x)").stdout("1\n");
+    run("let f = fn (x) => { print(x) }; f(1)").stdout("1\n");
+    command()
+        .write_stdin(parse("1 + 2"))
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_tag_field() {
+    let program = parse("print(1 + 2)").replace(r#""kind":"#, r#""type":"#);
+
+    command()
+        .arg("--tag-field=type")
+        .write_stdin(program.clone())
+        .assert()
+        .success()
+        .stdout("3\n");
+    command().write_stdin(program).assert().failure();
+}
+
+#[test]
+fn test_time() {
+    let cmd = command()
+        .arg("--time")
+        .write_stdin(parse("print(1 + 2)"))
+        .assert()
+        .success()
+        .stdout("3\n");
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert!(stderr.starts_with("tempo: "));
+    assert!(stderr.trim_end().ends_with('s'));
+}
+
+#[test]
+fn test_stats() {
+    let fib = parse(indoc! {"
+        let fib = fn (n) => {
+          if (n < 2) {
+            n
+          } else {
+            fib(n - 1) + fib(n - 2)
+          }
+        };
+        print(fib(10))
+    "});
+
+    // fib(10) makes 177 calls, 88 of which recurse with three more operations.
+    command()
+        .arg("--stats")
+        .write_stdin(fib)
+        .assert()
+        .success()
+        .stdout("55\n")
+        .stderr("chamadas: 177, operações: 441, condicionais: 177\n");
+}
+
+#[test]
+fn test_pure() {
+    let program = parse("let x = print(6 * 7); x");
+
+    command()
+        .arg("--pure")
+        .write_stdin(program.clone())
+        .assert()
+        .failure()
+        .stdout("efeitos colaterais não permitidos em modo puro\n");
+
+    // The value of the program is still written in the JSON format.
+    command()
+        .args(["--pure", "--format=json"])
+        .write_stdin(parse("let x = 6 * 7; x"))
+        .assert()
+        .success()
+        .stdout("42\n");
+
+    command()
+        .write_stdin(program)
+        .assert()
+        .success()
+        .stdout("42\n");
+}
+
+#[test]
+fn test_exit_code() {
+    let status = |source| run(source).failure().get_output().status.code().unwrap();
+
+    assert_eq!(status("print(1 + true)"), 1);
+    assert_eq!(status("print(1 / 0)"), 2);
+    assert_eq!(status("print(a)"), 3);
+    assert_eq!(status("let f = fn (a) => { a }; f(1, 2)"), 4);
+
+    let assert = command()
+        .write_stdin(r#"{"name": "test.rinha", "expression": {}}"#)
+        .assert()
+        .code(1);
+    let output = std::str::from_utf8(&assert.get_output().stdout).unwrap();
+    assert!(output.starts_with("Programa inválido: "), "{output}");
+}
+
+#[test]
+fn test_error_format() {
+    let assert = command()
+        .arg("--error-format=json")
+        .write_stdin(parse("print(1 / 0)"))
+        .assert()
+        .failure()
+        .stdout("");
+
+    let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+    let error: Value = serde_json::from_str(stderr).unwrap();
+    assert_eq!(
+        error,
+        json!({ "code": "E_DIV_ZERO", "message": "divisão por zero", "start": 6, "end": 11 })
+    );
+
+    // With the source at hand, the error also tells where it is in it.
+    let path = std::env::temp_dir().join(format!("rinha-error-{}.rinha", std::process::id()));
+    std::fs::write(&path, "let a = 1;\nprint(a / 0)\n").unwrap();
+    let assert = command()
+        .arg("--error-format=json")
+        .arg(&path)
+        .assert()
+        .failure();
+    std::fs::remove_file(path).unwrap();
+
+    let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+    let error: Value = serde_json::from_str(stderr).unwrap();
+    assert_eq!((&error["line"], &error["column"]), (&json!(2), &json!(7)));
+}
+
+#[test]
+fn test_error_position() {
+    // test.rinha isn't around, but the AST brings its source along.
+    let source = "let a = 1;\nprint(a / 0)";
+    let mut ast: Value = serde_json::from_str(&parse(source)).unwrap();
+    ast["source"] = json!(source);
+
+    command()
+        .write_stdin(ast.to_string())
+        .assert()
+        .failure()
+        .stdout("erro na linha 2, coluna 7: divisão por zero\n");
+
+    // Without it, there is only the message to show.
+    command()
+        .write_stdin(parse(source))
+        .assert()
+        .failure()
+        .stdout("divisão por zero\n");
+}
+
+#[test]
+fn test_dump_ast() {
+    let cmd = command()
+        .arg("--dump-ast")
+        .write_stdin(parse("print(1 + 2)"))
+        .assert()
+        .success();
+    let ast: Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+    assert_eq!(ast["expression"]["kind"], "Print");
+    assert_eq!(ast["expression"]["value"]["kind"], "Binary");
+    assert_eq!(ast["expression"]["value"]["op"], "Add");
+}
+
+#[test]
+fn test_list() {
+    let list =
+        |items: Vec<Value>| json!({ "kind": "List", "items": items, "location": location() });
+    let index = |target, index| json!({ "kind": "Index", "target": target, "index": index, "location": location() });
+    let numbers = || list(vec![term("1"), term("1 + 1"), term("3")]);
+
+    assert_eq!(ast!(print(numbers())), "[1, 2, 3]");
+    assert_eq!(ast!(print(list(vec![]))), "[]");
+    assert_eq!(ast!(print(index(numbers(), term("1")))), "2");
+    assert_eq!(ast!(print(unary("Length", numbers()))), "3");
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            numbers(),
+            list(vec![term("1"), term("2"), term("3")])
+        ))),
+        "true"
+    );
+    assert_eq!(
+        ast_err!(print(index(numbers(), term("3")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(index(numbers(), term("0 - 1")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(index(term("(1, 2)"), term("0")))),
+        "não é uma lista"
+    );
+
+    let tens = || list(vec![term("10"), term("20"), term("30")]);
+    assert_eq!(ast!(print(unary("First", tens()))), "10");
+    assert_eq!(ast!(print(unary("Second", tens()))), "20");
+    assert_eq!(
+        ast_err!(print(unary("First", list(vec![])))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(unary("Second", list(vec![term("10")])))),
+        "índice fora dos limites"
+    );
+}
+
+#[test]
+fn test_cons_head_tail() {
+    let list =
+        |items: Vec<Value>| json!({ "kind": "List", "items": items, "location": location() });
+    let cons =
+        |head, tail| json!({ "kind": "Cons", "head": head, "tail": tail, "location": location() });
+
+    assert_eq!(
+        ast!(print(cons(term("0"), list(vec![term("1")])))),
+        "[0, 1]"
+    );
+    assert_eq!(
+        ast!(print(unary("Head", list(vec![term("1"), term("2")])))),
+        "1"
+    );
+    assert_eq!(
+        ast!(print(unary("Tail", list(vec![term("1"), term("2")])))),
+        "[2]"
+    );
+    assert_eq!(ast_err!(print(unary("Head", list(vec![])))), "lista vazia");
+    assert_eq!(ast_err!(print(unary("Tail", list(vec![])))), "lista vazia");
+
+    // let sum = fn (xs) => { if (length(xs) == 0) { 0 } else { head(xs) + sum(tail(xs)) } };
+    let mut program = term(indoc! {"
+        let sum = fn (xs) => {
+          if (xs == 0) {
+            0
+          } else {
+            xs + sum(xs)
+          }
+        };
+        print(sum(xs))
+    "});
+    let body = &mut program["value"]["value"];
+    body["condition"]["lhs"] = unary("Length", term("xs"));
+    body["otherwise"]["lhs"] = unary("Head", term("xs"));
+    body["otherwise"]["rhs"]["arguments"][0] = unary("Tail", term("xs"));
+    program["next"]["value"]["arguments"][0] = list(vec![term("1"), term("2"), term("3")]);
+
+    assert_eq!(ast!(program), "6");
+}
+
+#[test]
+fn test_char_at() {
+    let char_at = |value, index| json!({ "kind": "CharAt", "value": value, "index": index, "location": location() });
+
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            char_at(term(r#""abc""#), term("1")),
+            term(r#""b""#)
+        ))),
+        "true"
+    );
+    assert_eq!(ast!(print(char_at(term(r#""héllo""#), term("1")))), "é");
+    assert_eq!(
+        ast_err!(print(char_at(term(r#""abc""#), term("3")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(char_at(term(r#""abc""#), term("0 - 1")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(char_at(term("123"), term("0")))),
+        "operação inválida"
+    );
+}
+
+#[test]
+fn test_substr() {
+    let substr = |value, start, len| json!({ "kind": "Substr", "value": value, "start": start, "len": len, "location": location() });
+    let hello = || term(r#""hello""#);
+
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            substr(hello(), term("1"), term("3")),
+            term(r#""ell""#)
+        ))),
+        "true"
+    );
+    assert_eq!(ast!(print(substr(hello(), term("3"), term("10")))), "lo");
+    assert_eq!(ast!(print(substr(hello(), term("10"), term("1")))), "");
+    assert_eq!(
+        ast!(print(substr(term(r#""héllo""#), term("1"), term("2")))),
+        "él"
+    );
+    assert_eq!(
+        ast_err!(print(substr(hello(), term("0 - 1"), term("1")))),
+        "não pode ser negativo"
+    );
+    assert_eq!(
+        ast_err!(print(substr(hello(), term("0"), term("0 - 1")))),
+        "não pode ser negativo"
+    );
+}
+
+#[test]
+fn test_rinha_source() {
+    let path = std::env::temp_dir().join(format!("rinha-source-{}.rinha", std::process::id()));
+    std::fs::write(
+        &path,
+        indoc! {"
+            let double = fn (n) => { n * 2 };
+            print(double(21))
+        "},
+    )
+    .unwrap();
+
+    command().arg(&path).assert().success().stdout("42\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_radix_literals() {
+    let path = std::env::temp_dir().join(format!("rinha-radix-{}.rinha", std::process::id()));
+    let run = |source: &str| {
+        std::fs::write(&path, source).unwrap();
+        command().arg(&path).assert()
+    };
+
+    run("print(0xFF)").success().stdout("255\n");
+    run("print(0b1010)").success().stdout("10\n");
+    run("print(0x7fffffff + 0B1)")
+        .success()
+        .stdout("2147483648\n");
+    run(r#"print("0xFF") // 0b1"#).success().stdout("0xFF\n");
+    run("let a0x1 = 1; print(a0x1)").success().stdout("1\n");
+    // Errors still point to the literals as written.
+    let assert = run("print(1 / 0b0)").failure();
+    let output = std::str::from_utf8(&assert.get_output().stdout).unwrap();
+    assert!(output.contains("print(1 / 0b0)"), "{output}");
+    assert!(output.contains("      ───┬───\n"), "{output}");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_read_line() {
+    let read_line = || json!({ "kind": "ReadLine", "location": location() });
+
+    let mut program = term("let a = 0; let b = 0; print((a, b))");
+    program["value"] = read_line();
+    program["next"]["value"] = read_line();
+    let ast = json!({ "name": "test.rinha", "expression": program });
+
+    let path = std::env::temp_dir().join(format!("rinha-read-line-{}.json", std::process::id()));
+    std::fs::write(&path, ast.to_string()).unwrap();
+
+    // The second read hits the end of the input.
+    command()
+        .arg(&path)
+        .write_stdin("hello\n")
+        .assert()
+        .success()
+        .stdout("(hello, )\n");
+
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(
+        ast_err!(print(read_line())),
+        "entrada indisponível, o programa precisa ser lido de um arquivo"
+    );
+}
+
+#[test]
+fn test_min_max() {
+    let call =
+        |kind, lhs, rhs| json!({ "kind": kind, "lhs": lhs, "rhs": rhs, "location": location() });
+
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            call("Min", term("3"), term("7")),
+            term("3")
+        ))),
+        "true"
+    );
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            call("Max", term("3"), term("7")),
+            term("7")
+        ))),
+        "true"
+    );
+    assert_eq!(ast!(print(call("Min", term("7"), term("0 - 3")))), "-3");
+    assert_eq!(ast!(print(call("Max", float(2.5), term("2")))), "2.5");
+    assert_eq!(
+        ast_err!(print(call("Max", term("1"), term("true")))),
+        "operação inválida entre Int e Bool"
+    );
+    assert_eq!(
+        ast_err!(print(call("Min", term(r#""a""#), term(r#""b""#)))),
+        "operação inválida entre Str e Str"
+    );
+}
+
+#[test]
+fn test_abs() {
+    let abs = |value| unary("Abs", value);
+    // abs(i64::MIN), which has to be computed since literals are i32.
+    let mut min_abs = term("let q = (2147483647 + 1) * (2147483647 + 1); (0 - q) - q");
+    min_abs["next"] = abs(min_abs["next"].take());
+
+    assert_eq!(
+        ast!(print(binary("Eq", abs(term("0 - 5")), term("5")))),
+        "true"
+    );
+    assert_eq!(ast!(print(abs(term("5")))), "5");
+    assert_eq!(ast!(print(abs(float(-1.5)))), "1.5");
+    assert_eq!(ast_err!(print(min_abs.clone())), "estouro de inteiro");
+    assert_eq!(
+        ast_err!(print(abs(term("true")))),
+        "esperava número, encontrou Bool"
+    );
+
+    let ast = json!({ "name": "test.rinha", "expression": print(min_abs) });
+    command()
+        .arg("--bignum")
+        .write_stdin(ast.to_string())
+        .assert()
+        .success()
+        .stdout("9223372036854775808\n");
+}
+
+#[test]
+fn test_long_let_chain() {
+    // Built as text, since serde_json would recurse through the nesting too.
+    let lets = 100_000;
+    let mut expression = String::new();
+    for i in 0..lets {
+        expression.push_str(&format!(
+            r#"{{"kind":"Let","name":{{"text":"a{i}","location":{{"start":0,"end":0}}}},"value":{{"kind":"Int","value":{i},"location":{{"start":0,"end":0}}}},"location":{{"start":0,"end":0}},"next":"#
+        ));
+    }
+    expression.push_str(&term("print(a99999)").to_string());
+    expression.push_str(&"}".repeat(lets));
+
+    // Reading it unoptimized takes more than the default stack.
+    command()
+        .env("RINHA_STACK_MB", "1024")
+        .write_stdin(format!(
+            r#"{{"name":"test.rinha","expression":{expression}}}"#
+        ))
+        .assert()
+        .success()
+        .stdout("99999\n");
+}
+
+#[test]
+fn test_get_env() {
+    let get_env = |key| unary("GetEnv", term(key));
+    let ast = json!({
+        "name": "test.rinha",
+        "expression": print(json!({
+            "kind": "Tuple",
+            "first": get_env(r#""RINHA_TEST""#),
+            "second": get_env(r#""RINHA_TEST_UNSET""#),
+            "location": location(),
+        })),
+    });
+
+    command()
+        .env("RINHA_TEST", "rinha")
+        .env_remove("RINHA_TEST_UNSET")
+        .write_stdin(ast.to_string())
+        .assert()
+        .success()
+        .stdout("(rinha, )\n");
+
+    assert_eq!(
+        ast_err!(print(get_env("1"))),
+        "esperava string, encontrou Int"
+    );
+}
+
+#[test]
+fn test_assert() {
+    assert_eq!(ast!(print(unary("Assert", term("1 + 1 == 2")))), "true");
+    assert_eq!(
+        ast_err!(print(unary("Assert", term("false")))),
+        "asserção falhou"
+    );
+    assert_eq!(
+        ast_err!(print(unary("Assert", term("1")))),
+        "esperava booleano, encontrou Int"
+    );
+}
+
+#[test]
+fn test_trace() {
+    let cmd = command()
+        .arg("--trace")
+        .write_stdin(parse("print(1 + 2)"))
+        .assert()
+        .success()
+        .stdout("3\n");
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert_eq!(
+        stderr,
+        indoc! {"
+            Print 0..12
+              Binary 6..11
+                Int 6..7
+                => 1
+                Int 10..11
+                => 2
+              => 3
+            => 3
+        "}
+    );
+
+    // Terms in tail position, like the body of a call, are traced too.
+    let cmd = command()
+        .arg("--trace")
+        .write_stdin(parse(
+            "let f = fn (n) => { if (n) { n } else { 0 } }; f(true)",
+        ))
+        .assert()
+        .success();
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert_eq!(
+        stderr,
+        indoc! {"
+            Let 0..54
+              Function 8..45
+              => <#closure/1>
+              Call 47..54
+                Var 47..48
+                => <#closure f/1>
+                Bool 49..53
+                => true
+                If 20..43
+                  Var 24..25
+                  => true
+                  Var 29..30
+                  => true
+                => true
+              => true
+            => true
+        "}
+    );
+}
+
+#[test]
+fn test_explain() {
+    let cmd = command()
+        .arg("--explain")
+        .write_stdin(parse("print(1 + 2)"))
+        .assert()
+        .success()
+        .stdout("3\n");
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert_eq!(
+        stderr,
+        indoc! {"
+            0..12 Print => 3
+              6..11 Binary => 3
+                6..7 Int => 1
+                10..11 Int => 2
+        "}
+    );
+
+    // Terms in tail position, like the branches of an if, are explained too.
+    let cmd = command()
+        .arg("--explain")
+        .write_stdin(parse("let a = 1; if (a == 1) { a + 1 } else { 0 }"))
+        .assert()
+        .success();
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert_eq!(
+        stderr,
+        indoc! {"
+            0..43 Let => 2
+              8..9 Int => 1
+              11..43 If => 2
+                15..21 Binary => true
+                  15..16 Var => 1
+                  20..21 Int => 1
+                25..30 Binary => 2
+                  25..26 Var => 1
+                  29..30 Int => 1
+        "}
+    );
+
+    let cmd = command()
+        .arg("--explain")
+        .write_stdin(parse("print(1 + true)"))
+        .assert()
+        .failure();
+    let stderr = std::str::from_utf8(&cmd.get_output().stderr).unwrap();
+    assert!(stderr.starts_with("0..15 Print !! falhou\n  6..14 Binary !! falhou\n"));
+}
+
+#[test]
+fn test_map() {
+    let empty = || json!({ "kind": "Map", "location": location() });
+    let insert = |map, key, value| json!({ "kind": "MapInsert", "map": map, "key": key, "value": value, "location": location() });
+    let get =
+        |map, key| json!({ "kind": "MapGet", "map": map, "key": key, "location": location() });
+    let map = || {
+        insert(
+            insert(empty(), term(r#""a""#), term("1")),
+            term("(1, 2)"),
+            term("true"),
+        )
+    };
+
+    assert_eq!(ast!(print(get(map(), term(r#""a""#)))), "1");
+    assert_eq!(ast!(print(get(map(), term("(1, 2)")))), "true");
+    assert_eq!(
+        ast!(print(get(
+            insert(map(), term(r#""a""#), term("2")),
+            term(r#""a""#)
+        ))),
+        "2"
+    );
+    assert_eq!(ast!(print(map())), "{(1, 2): true, a: 1}");
+    assert_eq!(
+        ast_err!(print(get(map(), term(r#""b""#)))),
+        "chave não encontrada"
+    );
+    assert_eq!(
+        ast_err!(print(insert(empty(), term("fn () => { 1 }"), term("1")))),
+        "Closure não pode ser usado como chave"
+    );
+
+    // Inserting doesn't change the map that was inserted into.
+    let mut program = term("let m = 0; let n = 0; print((m, n))");
+    program["value"] = insert(empty(), term("1"), term("1"));
+    program["next"]["value"] = insert(term("m"), term("2"), term("2"));
+    assert_eq!(ast!(program), "({1: 1}, {1: 1, 2: 2})");
+}
+
+#[test]
+fn test_while() {
+    let mut program = term(indoc! {"
+        let i = 0;
+        let sum = 0;
+        let last = 0;
+        print((sum, last))
+    "});
+    program["next"]["next"]["value"] = json!({
+        "kind": "While",
+        "condition": term("i < 100"),
+        "body": term("let i = i + 1; let sum = sum + i; i"),
+        "location": location(),
+    });
+    assert_eq!(ast!(program), "(5050, 100)");
+
+    let never = json!({
+        "kind": "While",
+        "condition": term("false"),
+        "body": term("1"),
+        "location": location(),
+    });
+    assert_eq!(ast!(print(never)), "");
+}
+
+#[test]
+fn test_assign() {
+    let assign = |name: &str, value| {
+        json!({
+            "kind": "Assign",
+            "name": { "text": name, "location": location() },
+            "value": value,
+            "location": location(),
+        })
+    };
+
+    let mut program = term("let x = 1; let y = 0; print((x, y))");
+    program["next"]["value"] = assign("x", term("x + 41"));
+    assert_eq!(ast!(program), "(42, 42)");
+
+    // Assignments are what let a while loop make progress.
+    let mut program = term(indoc! {"
+        let i = 0;
+        let sum = 0;
+        let _ = 0;
+        print(sum)
+    "});
+    program["next"]["next"]["value"] = json!({
+        "kind": "While",
+        "condition": term("i < 10"),
+        "body": json!({
+            "kind": "Let",
+            "name": { "text": "_", "location": location() },
+            "value": assign("i", term("i + 1")),
+            "next": assign("sum", term("sum + i")),
+            "location": location(),
+        }),
+        "location": location(),
+    });
+    assert_eq!(ast!(program), "55");
+
+    // A closure assigns to the variable it captured, not to a copy of it.
+    let mut program = term(indoc! {"
+        let count = 0;
+        let inc = fn () => { 0 };
+        let _ = inc();
+        let _ = inc();
+        let _ = inc();
+        print((count, inc()))
+    "});
+    program["next"]["value"]["value"] = assign("count", term("count + 1"));
+    assert_eq!(ast!(program), "(3, 4)");
+
+    assert_eq!(
+        ast_err!(print(assign("z", term("1")))),
+        "identificador não encontrado"
+    );
+}
+
+#[test]
+fn test_tags() {
+    let tag = |name: &str, value: Option<Value>| json!({ "kind": "Tag", "name": name, "value": value, "location": location() });
+    let unwrap_or_zero = |value| {
+        json!({
+            "kind": "MatchTag",
+            "value": value,
+            "arms": [
+                { "tag": "Some", "binding": { "text": "x", "location": location() }, "then": term("x") },
+                { "tag": "None", "then": term("0") },
+            ],
+            "location": location(),
+        })
+    };
+
+    assert_eq!(ast!(print(tag("Some", Some(term("5"))))), "Some(5)");
+    assert_eq!(ast!(print(tag("None", None))), "None");
+    assert_eq!(
+        ast!(print(unwrap_or_zero(tag("Some", Some(term("5")))))),
+        "5"
+    );
+    assert_eq!(ast!(print(unwrap_or_zero(tag("None", None)))), "0");
+    assert_eq!(
+        ast!(print(binary(
+            "Eq",
+            tag("Some", Some(term("5"))),
+            tag("Some", Some(term("5")))
+        ))),
+        "true"
+    );
+
+    assert_eq!(
+        ast_err!(print(unwrap_or_zero(tag("Ok", Some(term("1")))))),
+        "nenhum caso para Ok"
+    );
+    assert_eq!(
+        ast_err!(print(unwrap_or_zero(term("5")))),
+        "esperava valor com tag, encontrou Int"
+    );
+
+    // The arm's x is gone after the match, and the outer one is left alone.
+    let mut program = term("let x = 1; let y = 0; print((x, y))");
+    program["next"]["value"] = unwrap_or_zero(tag("Some", Some(term("5"))));
+    assert_eq!(ast!(program), "(1, 5)");
+}
+
+#[test]
+fn test_let_tuple() {
+    let var = |name: &str| json!({ "text": name, "location": location() });
+    let pattern =
+        |first, second| json!({ "first": first, "second": second, "location": location() });
+
+    // let (a, b) = (1, 2); a + b
+    let mut program = term("let _ = (1, 2); print(a + b)");
+    program["name"] = pattern(var("a"), var("b"));
+    assert_eq!(ast!(program), "3");
+
+    // let (a, (b, c)) = (1, (2, 3)); (c, b, a)
+    let mut program = term("let _ = (1, (2, 3)); print((c, (b, a)))");
+    program["name"] = pattern(var("a"), pattern(var("b"), var("c")));
+    assert_eq!(ast!(program), "(3, (2, 1))");
+
+    let mut program = term("let _ = 1; print(a)");
+    program["name"] = pattern(var("a"), var("b"));
+    assert_eq!(ast_err!(program), "esperava tupla, encontrou Int");
+}
+
+#[test]
+fn test_seq() {
+    let seq = |first, second| json!({ "kind": "Seq", "first": first, "second": second, "location": location() });
+
+    assert_eq!(ast!(print(seq(print(term("1")), term("2")))), "1\n2");
+    assert_eq!(
+        ast!(seq(
+            print(term("1")),
+            seq(print(term("2")), print(term("3")))
+        )),
+        "1\n2\n3"
+    );
+}
+
+#[test]
+fn test_string_interpolation() {
+    assert_eq!(rinha!(r#"print("sum = ${1 + 2}")"#), "sum = 3");
+    assert_eq!(
+        rinha!(r#"let x = 2; print("${x} * ${x} = ${if (x > 1) { x * x } else { 0 }}")"#),
+        "2 * 2 = 4"
+    );
+    assert_eq!(rinha_err!(r#"print("${1 + }")"#), "interpolação inválida");
+
+    let str = json!({ "kind": "Str", "value": r"\${1 + 2}", "location": location() });
+    assert_eq!(ast!(print(str)), "${1 + 2}");
+}
+
+#[test]
+fn test_type_predicates() {
+    assert_eq!(ast!(print(unary("IsInt", term("5")))), "true");
+    assert_eq!(ast!(print(unary("IsInt", term(r#""x""#)))), "false");
+    assert_eq!(ast!(print(unary("IsStr", term(r#""x""#)))), "true");
+    assert_eq!(ast!(print(unary("IsBool", term("1 == 1")))), "true");
+    assert_eq!(ast!(print(unary("IsTuple", term("(1, 2)")))), "true");
+    assert_eq!(ast!(print(unary("IsTuple", term("1")))), "false");
+    assert_eq!(
+        ast!(print(unary("IsFunction", term("fn () => { 1 }")))),
+        "true"
+    );
+    assert_eq!(ast!(print(unary("IsFunction", term("(1, 2)")))), "false");
+}
+
+#[test]
+fn test_bitwise() {
+    let int = |n: i64| json!({ "kind": "Int", "value": n, "location": location() });
+
+    assert_eq!(ast!(print(binary("BitAnd", int(6), int(3)))), "2");
+    assert_eq!(ast!(print(binary("BitOr", int(6), int(3)))), "7");
+    assert_eq!(ast!(print(binary("BitXor", int(6), int(3)))), "5");
+    assert_eq!(ast!(print(binary("Shl", int(1), int(4)))), "16");
+    assert_eq!(ast!(print(binary("Shr", int(-16), int(2)))), "-4");
+    assert_eq!(
+        ast_err!(print(binary("Shl", int(1), int(64)))),
+        "deslocamento fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(binary("Shr", int(1), int(-1)))),
+        "deslocamento fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(binary("BitAnd", int(1), term("true")))),
+        "operação inválida entre Int e Bool"
+    );
+}
+
+#[test]
+fn test_fold() {
+    let list =
+        |items: Vec<Value>| json!({ "kind": "List", "items": items, "location": location() });
+    let fold = |list, init, func| json!({ "kind": "Fold", "list": list, "init": init, "func": func, "location": location() });
+    let numbers = || list(vec![term("1"), term("2"), term("3")]);
+
+    assert_eq!(
+        ast!(print(fold(
+            numbers(),
+            term("0"),
+            term("fn (a, b) => { a + b }")
+        ))),
+        "6"
+    );
+    assert_eq!(
+        ast!(print(fold(
+            numbers(),
+            term(r#""""#),
+            term("fn (acc, n) => { acc + n }")
+        ))),
+        "123"
+    );
+    assert_eq!(ast!(print(fold(list(vec![]), term("0"), term("1")))), "0");
+    assert_eq!(
+        ast_err!(print(fold(numbers(), term("0"), term("fn (a) => { a }")))),
+        "esperava 1 argumento, recebeu 2"
+    );
+    assert_eq!(
+        ast_err!(print(fold(numbers(), term("0"), term("1")))),
+        "esperava função, encontrou Int"
+    );
+    assert_eq!(
+        ast_err!(print(fold(
+            term("(1, 2)"),
+            term("0"),
+            term("fn (a, b) => { a }")
+        ))),
+        "não é uma lista"
+    );
+}
+
+#[test]
+fn test_map_list() {
+    let list =
+        |items: Vec<Value>| json!({ "kind": "List", "items": items, "location": location() });
+    let map = |list, func| json!({ "kind": "MapList", "list": list, "func": func, "location": location() });
+    let numbers = || list(vec![term("1"), term("2"), term("3")]);
+
+    assert_eq!(
+        ast!(print(map(numbers(), term("fn (x) => { x * 2 }")))),
+        "[2, 4, 6]"
+    );
+    assert_eq!(
+        ast!(print(map(numbers(), term("fn (x) => { (x, x * x) }")))),
+        "[(1, 1), (2, 4), (3, 9)]"
+    );
+    assert_eq!(ast!(print(map(list(vec![]), term("1")))), "[]");
+    assert_eq!(
+        ast_err!(print(map(numbers(), term("fn (a, b) => { a }")))),
+        "esperava 2 argumentos, recebeu 1"
+    );
+    assert_eq!(
+        ast_err!(print(map(term("1"), term("fn (x) => { x }")))),
+        "não é uma lista"
+    );
+}
+
+#[test]
+fn test_range() {
+    let range =
+        |start, end| json!({ "kind": "Range", "start": start, "end": end, "location": location() });
+
+    assert_eq!(ast!(print(range(term("1"), term("5")))), "[1, 2, 3, 4]");
+    assert_eq!(ast!(print(range(term("5"), term("1")))), "[]");
+    assert_eq!(ast!(print(range(term("0 - 2"), term("1")))), "[-2, -1, 0]");
+    assert_eq!(
+        ast_err!(print(range(term("1"), term(r#""5""#)))),
+        "operação inválida entre Int e Str"
+    );
+
+    let program =
+        json!({ "name": "test.rinha", "expression": print(range(term("0"), term("1000"))) });
+    command()
+        .env("RINHA_MAX_STEPS", "100")
+        .write_stdin(program.to_string())
+        .assert()
+        .failure()
+        .stdout("intervalo grande demais\n");
+}
+
+#[test]
+fn test_tuple_n() {
+    let tuple = |items: &[&str]| json!({ "kind": "TupleN", "items": items.iter().map(|i| term(i)).collect::<Vec<_>>(), "location": location() });
+    let nth = |value, index| json!({ "kind": "Nth", "value": value, "index": index, "location": location() });
+
+    let triple = tuple(&["1", r#""a""#, "true"]);
+    assert_eq!(ast!(print(triple.clone())), "(1, a, true)");
+    assert_eq!(ast!(print(nth(triple.clone(), term("0")))), "1");
+    assert_eq!(ast!(print(nth(triple.clone(), term("2")))), "true");
+    assert_eq!(ast!(print(nth(term("(1, 2)"), term("1")))), "2");
+    assert_eq!(
+        ast_err!(print(nth(triple.clone(), term("3")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(nth(triple, term("0 - 1")))),
+        "índice fora dos limites"
+    );
+    assert_eq!(
+        ast_err!(print(nth(term("1"), term("0")))),
+        "esperava tupla, encontrou Int"
+    );
+}
+
+#[test]
+fn test_printf() {
+    let printf = |template: &str, args| json!({ "kind": "PrintF", "template": term(&format!("{template:?}")), "args": args, "location": location() });
+    let list = |items: &[&str]| json!({ "kind": "List", "items": items.iter().map(|i| term(i)).collect::<Vec<_>>(), "location": location() });
+    let tuple = |items: &[&str]| json!({ "kind": "TupleN", "items": items.iter().map(|i| term(i)).collect::<Vec<_>>(), "location": location() });
+
+    assert_eq!(
+        ast!(printf("{} + {} = {}", tuple(&["1", "2", "3"]))),
+        "1 + 2 = 3"
+    );
+    assert_eq!(ast!(printf("{}: {}", term(r#"("a", 1)"#))), "a: 1");
+    assert_eq!(
+        ast!(printf("[{:4}|{:4}]", list(&[r#""a""#, "1"]))),
+        "[a   |   1]"
+    );
+    assert_eq!(ast!(printf("{{{}}}", list(&["1"]))), "{1}");
+    let float = json!({ "kind": "Float", "value": 1.23456, "location": location() });
+    let floats = json!({ "kind": "List", "items": [float], "location": location() });
+    assert_eq!(ast!(printf("{:6.2}", floats)), "  1.23");
+    assert_eq!(ast!(printf("sem campos", list(&[]))), "sem campos");
+    assert_eq!(
+        ast_err!(printf("{} {}", list(&["1"]))),
+        "faltam argumentos para o modelo"
+    );
+    assert_eq!(
+        ast_err!(printf("{}", list(&["1", "2"]))),
+        "sobram argumentos para o modelo"
+    );
+    assert_eq!(
+        ast_err!(printf("{:x}", list(&["1"]))),
+        "formato inválido no modelo"
+    );
+    assert_eq!(
+        ast_err!(printf("{", list(&["1"]))),
+        "chave sem par no modelo"
+    );
+}
+
+#[test]
+fn test_case() {
+    let upper = |value| json!({ "kind": "Upper", "value": value, "location": location() });
+    let lower = |value| json!({ "kind": "Lower", "value": value, "location": location() });
+
+    assert_eq!(ast!(print(upper(term(r#""abc""#)))), "ABC");
+    assert_eq!(ast!(print(upper(term(r#""ß""#)))), "SS");
+    assert_eq!(ast!(print(lower(term(r#""ÀÉ Ok""#)))), "àé ok");
+    assert_eq!(
+        ast_err!(print(upper(term("1")))),
+        "esperava texto, encontrou Int"
+    );
+    assert_eq!(
+        ast_err!(print(lower(term("true")))),
+        "esperava texto, encontrou Bool"
+    );
+}
+
+#[test]
+fn test_split() {
+    let split = |value: &str, sep: &str| json!({ "kind": "Split", "value": term(value), "sep": term(sep), "location": location() });
+
+    assert_eq!(ast!(print(split(r#""a,b,c""#, r#"",""#))), "[a, b, c]");
+    assert_eq!(ast!(print(split(r#""a, b""#, r#"", ""#))), "[a, b]");
+    assert_eq!(ast!(print(split(r#""a,,b,""#, r#"",""#))), "[a, , b, ]");
+    assert_eq!(ast!(print(split(r#""abc""#, r#"";""#))), "[abc]");
+    assert_eq!(ast!(print(split(r#""aé""#, r#""""#))), "[a, é]");
+    assert_eq!(
+        ast_err!(print(split(r#""a1b""#, "1"))),
+        "operação inválida entre Str e Int"
+    );
+}
+
+#[test]
+fn test_string_predicates() {
+    let test = |kind, value: &str, pattern: &str| json!({ "kind": kind, "value": term(value), "pattern": term(pattern), "location": location() });
+
+    assert_eq!(
+        ast!(print(test("Contains", r#""rinha""#, r#""inh""#))),
+        "true"
+    );
+    assert_eq!(
+        ast!(print(test("Contains", r#""rinha""#, r#""x""#))),
+        "false"
+    );
+    assert_eq!(ast!(print(test("Contains", r#""rinha""#, r#""""#))), "true");
+    assert_eq!(
+        ast!(print(test("StartsWith", r#""rinha""#, r#""ri""#))),
+        "true"
+    );
+    assert_eq!(
+        ast!(print(test("StartsWith", r#""rinha""#, r#""ha""#))),
+        "false"
+    );
+    assert_eq!(
+        ast!(print(test("EndsWith", r#""rinha""#, r#""ha""#))),
+        "true"
+    );
+    assert_eq!(
+        ast!(print(test("EndsWith", r#""rinha""#, r#""ri""#))),
+        "false"
+    );
+    assert_eq!(
+        ast_err!(print(test("Contains", "1", r#""1""#))),
+        "operação inválida entre Int e Str"
+    );
+    assert_eq!(
+        ast_err!(print(test("EndsWith", r#""a""#, "true"))),
+        "operação inválida entre Str e Bool"
+    );
+}
+
+#[test]
+fn test_replace() {
+    let replace = |value: &str, from: &str, to: &str| json!({ "kind": "Replace", "value": term(value), "from": term(from), "to": term(to), "location": location() });
+
+    assert_eq!(ast!(print(replace(r#""aaa""#, r#""a""#, r#""b""#))), "bbb");
+    assert_eq!(ast!(print(replace(r#""a-b-c""#, r#""-""#, r#""""#))), "abc");
+    assert_eq!(ast!(print(replace(r#""abc""#, r#""x""#, r#""y""#))), "abc");
+    assert_eq!(ast!(print(replace(r#""aa""#, r#""a""#, r#""aa""#))), "aaaa");
+    assert_eq!(
+        ast_err!(print(replace(r#""abc""#, r#""""#, r#""x""#))),
+        "não pode ser vazio"
+    );
+    assert_eq!(
+        ast_err!(print(replace(r#""abc""#, r#""a""#, "1"))),
+        "esperava texto, encontrou Int"
+    );
+}
+
+#[test]
+fn test_trim() {
+    let trim =
+        |value: &str| json!({ "kind": "Trim", "value": term(value), "location": location() });
+
+    assert_eq!(ast!(print(trim(r#""  hi  ""#))), "hi");
+    assert_eq!(ast!(print(trim("\"\u{3000}a b\t\n\""))), "a b");
+    assert_eq!(ast!(print(trim(r#""""#))), "");
+    assert_eq!(ast_err!(print(trim("1"))), "esperava texto, encontrou Int");
+}
+
+#[test]
+fn test_repeat() {
+    let repeat = |value: &str, count: &str| json!({ "kind": "Repeat", "value": term(value), "count": term(count), "location": location() });
+
+    assert_eq!(ast!(print(repeat(r#""ab""#, "3"))), "ababab");
+    assert_eq!(ast!(print(repeat(r#""ab""#, "0"))), "");
+    assert_eq!(
+        ast_err!(print(repeat(r#""ab""#, "0 - 1"))),
+        "não pode ser negativo"
+    );
+    assert_eq!(
+        ast_err!(print(repeat(r#""ab""#, "2147483647 * 2147483647"))),
+        "texto grande demais"
+    );
+    assert_eq!(
+        ast_err!(print(repeat("1", "2"))),
+        "operação inválida entre Int e Int"
+    );
+}
+
+#[test]
+fn test_gensym() {
+    let gensym = || json!({ "kind": "Gensym", "location": location() });
+    let pair =
+        json!({ "kind": "Tuple", "first": gensym(), "second": gensym(), "location": location() });
+
+    assert_eq!(ast!(print(pair)), "(__g0, __g1)");
+}
+
+#[test]
+fn test_rand() {
+    let rand = |max: &str| json!({ "kind": "Rand", "max": term(max), "location": location() });
+    let pair = json!({ "kind": "Tuple", "first": rand("100"), "second": rand("100"), "location": location() });
+    let program = json!({ "name": "test.rinha", "expression": print(pair) });
+
+    for _ in 0..2 {
+        command()
+            .arg("--seed=42")
+            .write_stdin(program.to_string())
+            .assert()
+            .success()
+            .stdout("(13, 91)\n");
+    }
+    command()
+        .write_stdin(program.to_string())
+        .assert()
+        .success()
+        .stdout("(35, 0)\n");
+
+    assert_eq!(ast_err!(print(rand("0"))), "precisa ser positivo");
+    assert_eq!(
+        ast_err!(print(rand(r#""1""#))),
+        "esperava inteiro, encontrou Str"
+    );
+}
+
+#[test]
+fn test_div_mod() {
+    let div_mod = |lhs: &str, rhs: &str| json!({ "kind": "DivMod", "lhs": term(lhs), "rhs": term(rhs), "location": location() });
+
+    assert_eq!(ast!(print(div_mod("17", "5"))), "(3, 2)");
+    assert_eq!(ast!(print(div_mod("0 - 17", "5"))), "(-3, -2)");
+    assert_eq!(ast_err!(print(div_mod("17", "0"))), "divisão por zero");
+    assert_eq!(
+        ast_err!(print(div_mod(r#""17""#, "5"))),
+        "operação inválida entre Str e Int"
+    );
+
+    let min = json!({ "kind": "Int", "value": i64::MIN, "location": location() });
+    let program =
+        json!({ "kind": "DivMod", "lhs": min, "rhs": term("0 - 1"), "location": location() });
+    assert_eq!(ast_err!(print(program.clone())), "estouro de inteiro");
+
+    let ast = json!({ "name": "test.rinha", "expression": print(program) });
+    command()
+        .arg("--bignum")
+        .write_stdin(ast.to_string())
+        .assert()
+        .success()
+        .stdout("(9223372036854775808, 0)\n");
+}
+
+#[test]
+fn test_signum_clamp() {
+    let signum =
+        |value: &str| json!({ "kind": "Signum", "value": term(value), "location": location() });
+    let clamp = |value: &str, lo: &str, hi: &str| json!({ "kind": "Clamp", "value": term(value), "lo": term(lo), "hi": term(hi), "location": location() });
+
+    assert_eq!(ast!(print(signum("0 - 3"))), "-1");
+    assert_eq!(ast!(print(signum("0"))), "0");
+    assert_eq!(ast!(print(signum("42"))), "1");
+    assert_eq!(
+        ast_err!(print(signum("true"))),
+        "esperava inteiro, encontrou Bool"
+    );
+
+    assert_eq!(ast!(print(clamp("10", "0", "5"))), "5");
+    assert_eq!(ast!(print(clamp("0 - 10", "0", "5"))), "0");
+    assert_eq!(ast!(print(clamp("3", "0", "5"))), "3");
+    assert_eq!(ast!(print(clamp("3", "3", "3"))), "3");
+    assert_eq!(
+        ast_err!(print(clamp("3", "5", "0"))),
+        "limite inferior maior que o superior"
+    );
+    assert_eq!(
+        ast_err!(print(clamp("3", r#""0""#, "5"))),
+        "esperava inteiro, encontrou Str"
+    );
+}
+
+#[test]
+fn test_gcd_lcm() {
+    let call = |kind, lhs: &str, rhs: &str| json!({ "kind": kind, "lhs": term(lhs), "rhs": term(rhs), "location": location() });
+    let int = |value: i64| json!({ "kind": "Int", "value": value, "location": location() });
+
+    assert_eq!(ast!(print(call("Gcd", "12", "18"))), "6");
+    assert_eq!(ast!(print(call("Gcd", "0 - 12", "18"))), "6");
+    assert_eq!(ast!(print(call("Gcd", "7", "0"))), "7");
+    assert_eq!(ast!(print(call("Gcd", "0", "0"))), "0");
+    assert_eq!(ast!(print(call("Lcm", "4", "6"))), "12");
+    assert_eq!(ast!(print(call("Lcm", "0 - 4", "6"))), "12");
+    assert_eq!(ast!(print(call("Lcm", "0", "6"))), "0");
+    assert_eq!(
+        ast_err!(print(call("Gcd", "1", "true"))),
+        "operação inválida entre Int e Bool"
+    );
+
+    let lcm = json!({ "kind": "Lcm", "lhs": int(i64::MAX), "rhs": int(2), "location": location() });
+    assert_eq!(ast_err!(print(lcm.clone())), "estouro de inteiro");
+    let ast = json!({ "name": "test.rinha", "expression": print(lcm) });
+    command()
+        .arg("--bignum")
+        .write_stdin(ast.to_string())
+        .assert()
+        .success()
+        .stdout("18446744073709551614\n");
+}
@@ -0,0 +1,347 @@
+use std::{collections::HashMap, hint::black_box, time::Instant};
+
+use indoc::indoc;
+use miette::Diagnostic;
+use rinha_compiladores::{
+    analyze, compile, compile_with_tag, error::ErrorKind, eval, fold_constants, run, run_json,
+    warnings, Binary, BinaryOp, File, Int, Interpreter, Let, Location, Parameter, Pattern, Scope,
+    Symbol, Term, Val, Var,
+};
+
+fn location() -> Location {
+    Location { start: 0, end: 0 }
+}
+
+fn parse(source: &str) -> File {
+    let ast = rinha::parser::parse_or_report("test.rinha", source).unwrap();
+    serde_json::from_value(serde_json::to_value(ast).unwrap()).unwrap()
+}
+
+#[test]
+fn test_eval() {
+    // let a = 40; a + 2
+    let term = Term::Let(Box::new(Let {
+        name: Pattern::Var(Parameter {
+            text: "a".into(),
+            location: location(),
+        }),
+        value: Term::Int(Int {
+            value: 40,
+            location: location(),
+        }),
+        next: Term::Binary(Box::new(Binary {
+            lhs: Term::Var(Var {
+                text: "a".into(),
+                location: location(),
+            }),
+            op: BinaryOp::Add,
+            rhs: Term::Int(Int {
+                value: 2,
+                location: location(),
+            }),
+            location: location(),
+        })),
+        location: location(),
+    }));
+
+    let scope = Scope::default();
+    assert_eq!(eval(&term, &scope).unwrap(), Val::Int(42));
+    assert_eq!(scope.get("a"), Some(Val::Int(40)));
+}
+
+#[test]
+fn test_run() {
+    let file = parse(indoc! {"
+        let fib = fn (n) => {
+          if (n < 2) {
+            n
+          } else {
+            fib(n - 1) + fib(n - 2)
+          }
+        };
+        fib(10)
+    "});
+
+    assert_eq!(run(file).unwrap(), Val::Int(55));
+}
+
+#[test]
+fn test_run_error() {
+    let error = run(parse("1 / 0")).unwrap_err();
+    assert_eq!(error.to_string(), "divisão por zero");
+}
+
+#[test]
+fn test_error_position() {
+    let source = indoc! {"
+        let a = 1;
+        let b = 0;
+        print(a / b)
+    "};
+
+    let error = run(parse(source)).unwrap_err().with_source(source);
+    assert_eq!(
+        error.to_string(),
+        "erro na linha 3, coluna 7: divisão por zero"
+    );
+}
+
+#[test]
+fn test_unknown_identifier() {
+    let error = run(parse("let a = 1; a + b")).unwrap_err();
+    match error.kind() {
+        ErrorKind::UnknowIdentifier(var) => assert_eq!(var.text.as_str(), "b"),
+        kind => panic!("unexpected error {kind:?}"),
+    }
+}
+
+/// Looking variables up by their interned symbols against doing it by their
+/// names. Timing dependent, so it only runs when asked for, with
+/// `cargo test --release -- --ignored bench_symbol_lookup`.
+#[test]
+#[ignore]
+fn bench_symbol_lookup() {
+    let names = (0..64).map(|i| format!("variable_{i}")).collect::<Vec<_>>();
+    let by_name = names
+        .iter()
+        .map(|name| (name.clone(), Val::Int(1)))
+        .collect::<HashMap<_, _>>();
+    let by_symbol = names
+        .iter()
+        .map(|name| (Symbol::from(name.as_str()), Val::Int(1)))
+        .collect::<HashMap<_, _>>();
+    let symbols = names
+        .iter()
+        .map(|name| Symbol::from(name.as_str()))
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for _ in 0..10_000 {
+        for name in &names {
+            black_box(by_name.get(black_box(name)));
+        }
+    }
+    let names_took = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..10_000 {
+        for symbol in &symbols {
+            black_box(by_symbol.get(black_box(symbol)));
+        }
+    }
+    let symbols_took = start.elapsed();
+
+    println!("nomes: {names_took:?}, símbolos: {symbols_took:?}");
+    assert!(symbols_took < names_took);
+}
+
+#[test]
+fn test_invalid_condition() {
+    let error = run(parse("if (1) { 2 } else { 3 }")).unwrap_err();
+    assert_eq!(error.to_string(), "esperava booleano, encontrou Int");
+
+    let labels = error.labels().unwrap().collect::<Vec<_>>();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(
+        labels[0].label(),
+        Some("esta expressão precisa ser um booleano")
+    );
+    assert_eq!((labels[0].offset(), labels[0].len()), (4, 1));
+}
+
+#[test]
+fn test_print_output() {
+    let file = parse("print(print(1) + 1)");
+
+    let mut out = Vec::new();
+    let val = Interpreter::new(&mut out).run(&file).unwrap();
+
+    assert_eq!(val, Val::Int(2));
+    assert_eq!(String::from_utf8(out).unwrap(), "1\n2\n");
+}
+
+#[test]
+fn test_error_codes() {
+    let code = |source| run(parse(source)).unwrap_err().code();
+
+    assert_eq!(code("1 / 0"), "E_DIV_ZERO");
+    assert_eq!(code("a"), "E_UNKNOWN_IDENT");
+    assert_eq!(code("let f = f; 0"), "E_USE_BEFORE_DEF");
+    assert_eq!(code("let f = fn (a) => { a }; f(1, 2)"), "E_ARITY");
+    assert_eq!(code("first(1)"), "E_TYPE");
+    assert_eq!(code("1 + true"), "E_TYPE");
+    assert_eq!(code("if (1) { 2 } else { 3 }"), "E_TYPE");
+    assert_eq!(code("2147483647 * 2147483647 * 2147483647"), "E_OVERFLOW");
+    assert_eq!(code(r#""${1 + }""#), "E_ARGUMENT");
+
+    let index: Term = serde_json::from_value(serde_json::json!({
+        "kind": "Index",
+        "target": { "kind": "List", "items": [], "location": location() },
+        "index": { "kind": "Int", "value": 0, "location": location() },
+        "location": location(),
+    }))
+    .unwrap();
+    assert_eq!(
+        eval(&index, &Scope::default()).unwrap_err().code(),
+        "E_INDEX"
+    );
+
+    let mut interpreter = Interpreter::new(std::io::sink());
+    interpreter.max_steps = 100;
+    let error = interpreter
+        .run(&parse("let f = fn (n) => { f(n + 1) }; f(0)"))
+        .unwrap_err();
+    assert_eq!(error.code(), "E_RECURSION");
+
+    let mut interpreter = Interpreter::new(std::io::sink());
+    interpreter.deadline = Some(Instant::now());
+    let error = interpreter
+        .run(&parse("let f = fn (n) => { f(n + 1) }; f(0)"))
+        .unwrap_err();
+    assert_eq!(error.code(), "E_TIMEOUT");
+}
+
+#[test]
+fn test_compile() {
+    let ast = rinha::parser::parse_or_report("test.rinha", "n * 2").unwrap();
+    let program = compile(&serde_json::to_string(&ast).unwrap()).unwrap();
+
+    let scope = Scope::default();
+    scope.set("n", Val::Int(1));
+    assert_eq!(program.run(&scope).unwrap(), Val::Int(2));
+
+    let scope = Scope::default();
+    scope.set("n", Val::Int(21));
+    assert_eq!(program.run(&scope).unwrap(), Val::Int(42));
+
+    assert!(compile("{}").is_err());
+}
+
+#[test]
+fn test_compile_with_tag() {
+    let ast = rinha::parser::parse_or_report("test.rinha", "let a = 40; a + 2").unwrap();
+    let json = serde_json::to_string(&ast)
+        .unwrap()
+        .replace(r#""kind":"#, r#""type":"#);
+
+    let program = compile_with_tag(&json, "type").unwrap();
+    assert_eq!(program.run(&Scope::default()).unwrap(), Val::Int(42));
+
+    assert!(compile(&json).is_err());
+    assert!(compile_with_tag(&json, "kind").is_err());
+}
+
+#[test]
+fn test_run_json() {
+    let json = |source| {
+        let ast = rinha::parser::parse_or_report("test.rinha", source).unwrap();
+        serde_json::to_string(&ast).unwrap()
+    };
+
+    assert_eq!(run_json(&json("let _ = print(1); print(2)")), "1\n2\n");
+    assert_eq!(
+        run_json(&json("let _ = print(1); 1 / 0")),
+        "1\ndivisão por zero\n"
+    );
+    assert!(run_json("{}").starts_with("programa inválido"));
+}
+
+#[test]
+fn test_analyze() {
+    let errors = analyze(&parse("let a = 1; print(b + a / 0)"));
+    let messages = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        ["identificador não encontrado", "divisão por zero"]
+    );
+    assert_eq!(errors[0].code(), "E_UNKNOWN_IDENT");
+
+    let errors = analyze(&parse(indoc! {"
+        let f = fn (n) => {
+          if (n == 0) { 0 } else { f(n - 1, 1) }
+        };
+        let x = x + 1;
+        f(1)
+    "}));
+    let codes = errors.iter().map(|e| e.code()).collect::<Vec<_>>();
+    assert_eq!(codes, ["E_ARITY", "E_USE_BEFORE_DEF"]);
+
+    let errors = analyze(&parse(indoc! {"
+        let ping = fn (n) => { if (n == 0) { 0 } else { pong(n - 1) } };
+        let pong = fn (n) => { if (n == 0) { 1 } else { ping(n - 1) } };
+        ping(3)
+    "}));
+    assert!(errors.is_empty(), "{errors:?}");
+
+    // Nothing is evaluated, so nothing is printed either.
+    assert!(analyze(&parse("let x = 1; print(x)")).is_empty());
+}
+
+#[test]
+fn test_fold_constants() {
+    let term = fold_constants(parse("2 * 3 + 1").expression);
+    assert!(matches!(term, Term::Int(Int { value: 7, .. })), "{term:?}");
+
+    let term = fold_constants(parse("1 / 0").expression);
+    assert!(matches!(term, Term::Binary(_)), "{term:?}");
+
+    let term = fold_constants(parse("let f = fn (x) => { x + (2 * 3) }; f(1)").expression);
+    assert_eq!(eval(&term, &Scope::default()).unwrap(), Val::Int(7));
+
+    // Folded the way the program will compute it with --int-width.
+    let mut interpreter = Interpreter::new(std::io::sink());
+    interpreter.int_width = Some(32);
+    let term = interpreter.fold_constants(parse("2147483647 + 1").expression);
+    assert!(
+        matches!(
+            term,
+            Term::Int(Int {
+                value: -2147483648,
+                ..
+            })
+        ),
+        "{term:?}"
+    );
+}
+
+#[test]
+fn test_fold_if() {
+    let term = fold_constants(parse("if (true) { 1 } else { 2 }").expression);
+    assert!(matches!(term, Term::Int(Int { value: 1, .. })), "{term:?}");
+
+    let term = fold_constants(parse("if (1 > 2) { 1 } else { 2 }").expression);
+    assert!(matches!(term, Term::Int(Int { value: 2, .. })), "{term:?}");
+
+    let term = fold_constants(parse("let a = true; if (a) { 1 } else { 2 }").expression);
+    let Term::Let(t) = term else {
+        panic!("{term:?}")
+    };
+    assert!(matches!(t.next, Term::If(_)), "{:?}", t.next);
+}
+
+#[test]
+fn test_warnings() {
+    let source = indoc! {"
+        let a = 1;
+        let b = 2;
+        let _ = print(a);
+        let c = 3;
+        let f = fn (n) => { if (n == 0) { c } else { f(n - 1) } };
+        let a = f(1);
+        0
+    "};
+
+    let warnings = warnings(&parse(source));
+    let found = warnings
+        .iter()
+        .map(|w| (w.to_string(), &source[w.location.start..w.location.end]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            ("'b' nunca é usado".to_string(), "b"),
+            ("'a' nunca é usado".to_string(), "a"),
+        ]
+    );
+    assert_eq!(warnings[1].location.start, source.rfind("a =").unwrap());
+}